@@ -0,0 +1,86 @@
+//! Evaluation throughput over the yacht dataset, in nodes evaluated per second
+//! (program size times number of instances, per second of evaluation).
+//!
+//! Run with `cargo bench`.
+
+extern crate nodevo;
+
+use std::time::Instant;
+use nodevo::core::data::Data;
+use nodevo::core::evaluator::{Program, Scratch};
+use nodevo::core::individual::Individual;
+use nodevo::core::Float;
+use nodevo::core::matrix::Matrix;
+use nodevo::core::node::Node;
+use nodevo::core::utils::rng_from_seed;
+
+/// Reference recursive evaluation through `Node::op`, one `Vec` per node.
+fn recursive(core: &[Node], idx: &mut usize, df: &Matrix) -> Vec<Float> {
+    let node = &core[*idx];
+    match *node {
+        Node::Constant(val) => vec![val; df.rows()],
+        Node::Input(j) => df.column(j).to_vec(),
+        _ => {
+            let mut args = vec![];
+            for _child_node in 0..node.arity() {
+                *idx += 1;
+                args.push(recursive(core, idx, df));
+            }
+            node.op(args)
+        }
+    }
+}
+
+/// Runs `f` over all programs `rounds` times and prints the throughput.
+fn bench<F>(name: &str, programs: &[Individual], rows: usize, rounds: usize, mut f: F)
+    where F: FnMut(&Individual) -> Float
+{
+    let nodes: usize = programs.iter().map(|p| p.size()).sum();
+    let mut checksum = 0.0 as Float;
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for p in programs {
+            checksum += f(p);
+        }
+    }
+    let secs = start.elapsed().as_secs_f64();
+    let throughput = (nodes * rows * rounds) as f64 / secs;
+    println!("{:<28} {:>10.3} Mnodes/s  ({:.3} s, checksum {})",
+             name,
+             throughput / 1e6,
+             secs,
+             checksum);
+}
+
+fn main() {
+    let ds = Data::new("yacht");
+    let mut rng = rng_from_seed(2017);
+    for &depth in &[4, 6, 8] {
+        let programs: Vec<Individual> =
+            (0..500).map(|_| Individual::full(depth, &ds, &mut rng)).collect();
+        let rows = ds.train().rows();
+        let rounds = 20;
+        println!("full trees of depth {} ({} programs, {} rows):",
+                 depth,
+                 programs.len(),
+                 rows);
+
+        bench("recursive (Node::op)", &programs, rows, rounds, |p| {
+            recursive(p.core(), &mut 0, ds.train())[0]
+        });
+
+        let compiled: Vec<Program> = programs.iter().map(|p| Program::compile(p.core())).collect();
+        let mut idx = 0;
+        let mut scratch = Scratch::new();
+        bench("compiled (precompiled)", &programs, rows, rounds, |_| {
+            let out = compiled[idx % compiled.len()].eval_with(ds.train(), &mut scratch)[0];
+            idx += 1;
+            out
+        });
+
+        bench("compiled (compile + eval)", &programs, rows, rounds, |p| {
+            Program::compile(p.core()).eval(ds.train())[0]
+        });
+        println!();
+    }
+}
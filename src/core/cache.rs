@@ -0,0 +1,290 @@
+//! Caching of subtree semantics across generations of standard GP.
+//!
+//! Offspring of standard crossover and mutation share most of their subtrees with their
+//! parents. A `SemanticCache` keeps the semantics of every functional subtree of the
+//! current population, keyed by the subtree itself (hashed structurally), so that when
+//! evaluating offspring only the subtrees that did not exist in the parents' generation
+//! (i.e. the path from the modified region to the root) are computed.
+//!
+//! During a generation the cache is read-only for lookups and offspring subtrees are
+//! recorded aside; `new_generation()` then makes the latter the entries to look up.
+//! Every functional subtree of an offspring is recorded, including those inside a
+//! subtree found in the cache, whose entries are carried over.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use core::data::Data;
+use core::node::Node;
+use core::utils::{add_assign, subtract_assign, multiply_assign, divide_assign, cosine_assign,
+                  logistic_assign};
+use core::Float;
+
+/// Semantics of a subtree on training and test data.
+type Semantics = (Vec<Float>, Vec<Float>);
+
+/// A subtree in prefix notation, hashed and compared bitwise.
+#[derive(Debug, Clone)]
+struct SubtreeKey(Vec<Node>);
+
+impl Hash for SubtreeKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for node in &self.0 {
+            match *node {
+                Node::Constant(val) => (6u8, val.to_bits()).hash(state),
+                Node::Input(j) => (7u8, j).hash(state),
+                Node::Addition => 0u8.hash(state),
+                Node::Subtraction => 1u8.hash(state),
+                Node::Multiplication => 2u8.hash(state),
+                Node::Division => 3u8.hash(state),
+                Node::Cosine => 4u8.hash(state),
+                Node::LogFunction => 5u8.hash(state),
+            }
+        }
+    }
+}
+
+impl PartialEq for SubtreeKey {
+    fn eq(&self, other: &SubtreeKey) -> bool {
+        self.0.len() == other.0.len() &&
+        self.0.iter().zip(&other.0).all(|(a, b)| match (a, b) {
+            (&Node::Constant(x), &Node::Constant(y)) => x.to_bits() == y.to_bits(),
+            _ => a == b,
+        })
+    }
+}
+
+impl Eq for SubtreeKey {}
+
+/// Hit and miss counts of a `SemanticCache`, over functional subtrees.
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    /// Subtrees whose semantics were found in the cache.
+    pub hits: usize,
+    /// Subtrees whose semantics had to be computed.
+    pub misses: usize,
+    /// Number of subtrees available for lookup in the current generation.
+    pub entries: usize,
+}
+
+impl CacheStats {
+    /// Returns the fraction of lookups that were hits.
+    pub fn hit_rate(&self) -> f32 {
+        if self.hits + self.misses == 0 {
+            0.0
+        } else {
+            self.hits as f32 / (self.hits + self.misses) as f32
+        }
+    }
+}
+
+/// A cache of subtree semantics. See the module documentation.
+#[derive(Debug, Default)]
+pub struct SemanticCache {
+    /// Subtrees of the current population, looked up while breeding.
+    entries: HashMap<SubtreeKey, Arc<Semantics>>,
+    /// Subtrees of the offspring bred so far.
+    recorded: Mutex<HashMap<SubtreeKey, Arc<Semantics>>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl SemanticCache {
+    /// Returns an empty cache.
+    pub fn new() -> SemanticCache {
+        SemanticCache::default()
+    }
+
+    /// Returns the semantics of the program `core` on `data`, reusing the semantics of
+    /// the subtrees found in the cache and recording those of all its subtrees for the
+    /// next generation. Results are identical to `Individual::compute_semantics()`.
+    pub fn semantics(&self, core: &[Node], data: &Data) -> (Vec<Float>, Vec<Float>) {
+        self.counted_semantics(core, data, true)
+    }
+
+    /// Records the subtrees of `programs`, e.g. the current population, as the entries to
+    /// look up (see `new_generation()`). Lookups made here do not count in `stats()`.
+    pub fn warm_up<'a, I>(&mut self, programs: I, data: &Data)
+        where I: IntoIterator<Item = &'a [Node]>
+    {
+        for core in programs {
+            self.counted_semantics(core, data, false);
+        }
+        self.new_generation();
+    }
+
+    /// `semantics()`, counting hits and misses if `count`.
+    fn counted_semantics(&self, core: &[Node], data: &Data, count: bool) -> Semantics {
+        let mut recorded = vec![];
+        let (train, test) = self.inner_semantics(core, &mut 0, data, count, &mut recorded);
+        self.recorded
+            .lock()
+            .expect("@SemanticCache::semantics() Poisoned lock.")
+            .extend(recorded);
+        (train.into_owned(), test.into_owned())
+    }
+
+    /// Recursive component of `semantics()`. Inputs are borrowed from `data`.
+    fn inner_semantics<'a>(&self,
+                           core: &[Node],
+                           idx: &mut usize,
+                           data: &'a Data,
+                           count: bool,
+                           recorded: &mut Vec<(SubtreeKey, Arc<Semantics>)>)
+                           -> (Cow<'a, [Float]>, Cow<'a, [Float]>) {
+        let node = &core[*idx];
+        match *node {
+            Node::Constant(val) => {
+                (Cow::Owned(vec![val; data.train().rows()]),
+                 Cow::Owned(vec![val; data.test().rows()]))
+            }
+            Node::Input(j) => {
+                (Cow::Borrowed(data.train().column(j)), Cow::Borrowed(data.test().column(j)))
+            }
+            _ => {
+                let key = SubtreeKey(core[*idx..*idx + subtree_len(core, *idx)].to_vec());
+                if let Some(sems) = self.entries.get(&key) {
+                    if count {
+                        self.hits.fetch_add(1, Ordering::Relaxed);
+                    }
+                    *idx += key.0.len() - 1;
+                    let (train, test) = (sems.0.clone(), sems.1.clone());
+                    self.carry_inner(&key.0, recorded);
+                    recorded.push((key, sems.clone()));
+                    return (Cow::Owned(train), Cow::Owned(test));
+                }
+                if count {
+                    self.misses.fetch_add(1, Ordering::Relaxed);
+                }
+                let mut train_args = vec![];
+                let mut test_args = vec![];
+                for _child_node in 0..node.arity() {
+                    *idx += 1;
+                    let (train, test) = self.inner_semantics(core, idx, data, count, recorded);
+                    train_args.push(train);
+                    test_args.push(test);
+                }
+                let sems = (apply(node, train_args), apply(node, test_args));
+                recorded.push((key, Arc::new(sems.clone())));
+                (Cow::Owned(sems.0), Cow::Owned(sems.1))
+            }
+        }
+    }
+
+    /// Records the entries of the functional subtrees inside `subtree`, a hit, which
+    /// were recorded along with it.
+    fn carry_inner(&self, subtree: &[Node], recorded: &mut Vec<(SubtreeKey, Arc<Semantics>)>) {
+        for start in 1..subtree.len() {
+            if subtree[start].arity() == 0 {
+                continue;
+            }
+            let key = SubtreeKey(subtree[start..start + subtree_len(subtree, start)].to_vec());
+            if let Some(sems) = self.entries.get(&key) {
+                recorded.push((key, sems.clone()));
+            }
+        }
+    }
+
+    /// Makes the subtrees recorded since the last call the ones to look up,
+    /// discarding the others. To be called once per generation.
+    pub fn new_generation(&mut self) {
+        let recorded = self.recorded
+            .get_mut()
+            .expect("@SemanticCache::new_generation() Poisoned lock.");
+        self.entries = mem::take(recorded);
+    }
+
+    /// Returns hit and miss counts since the cache was created, excluding `warm_up()`.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.len(),
+        }
+    }
+}
+
+/// Applies the functional `node` to its arguments, as `Node::op` does, writing over
+/// the first one (copied if it is borrowed).
+fn apply(node: &Node, args: Vec<Cow<[Float]>>) -> Vec<Float> {
+    let mut args = args.into_iter();
+    let mut out = args.next().expect("@cache::apply() Missing argument.").into_owned();
+    match *node {
+        Node::Addition => add_assign(&mut out, &args.next().unwrap()),
+        Node::Subtraction => subtract_assign(&mut out, &args.next().unwrap()),
+        Node::Multiplication => multiply_assign(&mut out, &args.next().unwrap()),
+        Node::Division => divide_assign(&mut out, &args.next().unwrap()),
+        Node::Cosine => cosine_assign(&mut out),
+        Node::LogFunction => logistic_assign(&mut out),
+        _ => panic!("@cache::apply() Called on a non-functional node."),
+    }
+    out
+}
+
+/// Returns the number of nodes of the subtree starting at `idx`.
+fn subtree_len(core: &[Node], idx: usize) -> usize {
+    let mut pending = 1;
+    let mut len = 0;
+    while pending > 0 {
+        pending += core[idx + len].arity();
+        pending -= 1;
+        len += 1;
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::individual::Individual;
+    use core::utils::rng_from_seed;
+
+    #[test]
+    fn cached_semantics_are_identical_to_computed_ones() {
+        let data = Data::new("yacht");
+        let mut rng = rng_from_seed(2);
+        let mut cache = SemanticCache::new();
+        let mut programs: Vec<Vec<Node>> =
+            (0..100).map(|_| Individual::grow(6, &data, &mut rng).clone_core()).collect();
+        cache.warm_up(programs.iter().map(|p| p.as_slice()), &data);
+        // programs sharing subtrees with the previous ones, as offspring do.
+        let offspring: Vec<Vec<Node>> = (0..100)
+            .map(|i| {
+                let mut core = vec![Node::Addition];
+                core.extend(programs[i].iter().cloned());
+                core.extend(programs[99 - i].iter().cloned());
+                core
+            })
+            .collect();
+        programs.extend(offspring);
+        for core in &programs {
+            let mut ind = Individual::from_core(core.clone());
+            ind.compute_semantics(&data);
+            assert_eq!(cache.semantics(core, &data), ind.semantics());
+        }
+        assert!(cache.stats().hits > 0);
+    }
+
+    #[test]
+    fn inner_subtrees_of_hits_stay_cached() {
+        use core::node::Node::*;
+        let data = Data::new("yacht");
+        let mut cache = SemanticCache::new();
+        // (x0 + x1) * x0
+        let parent = [Multiplication, Addition, Input(0), Input(1), Input(0)];
+        cache.warm_up(vec![&parent[..]], &data);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (0, 0, 2));
+
+        cache.semantics(&parent, &data);
+        cache.new_generation();
+        assert_eq!(cache.stats().entries, 2);
+        // (x0 + x1) - 1 finds x0 + x1, although it was only inside a hit.
+        cache.semantics(&[Subtraction, Addition, Input(0), Input(1), Constant(1.0)], &data);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+    }
+}
@@ -0,0 +1,249 @@
+//! Classification with programs evolved for regression.
+//!
+//! Binary problems have targets `0.0` and `1.0` (see `Data::new_classification()` and
+//! `Data::one_vs_rest()`). The output of a program is either thresholded, the instance
+//! being of class `1` when the output is above the threshold, or taken through the
+//! logistic function as the probability of class `1`. `Binary` provides the matching
+//! fitness functions for `GP::set_fitness()`.
+//!
+//! Multi-class problems are decomposed one-vs-rest: a binary classifier is evolved per
+//! class, and an instance is assigned the class whose classifier is the most confident.
+
+use core::Float;
+use core::data::Data;
+use core::fitness::Fitness;
+use core::gp::GP;
+use core::individual::Individual;
+use core::matrix::Matrix;
+use core::utils::logistic;
+
+/// Smallest probability considered by the log-loss, which is unbounded otherwise.
+const PROBABILITY_LIMIT: Float = 1e-7;
+
+/// Fitness functions for binary classification. As all errors, lower is fitter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binary {
+    /// `1 - accuracy`, outputs above the given threshold being of class `1`.
+    /// Non-finite outputs are misclassified.
+    Accuracy(Float),
+    /// `1 - F1 score` of class `1`, outputs above the given threshold being of class `1`.
+    /// Non-finite outputs are misclassified.
+    F1(Float),
+    /// Cross-entropy of the targets and the logistic function of the outputs.
+    LogLoss,
+}
+
+impl Fitness for Binary {
+    fn error(&self, outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> Float {
+        if outputs.len() != targets.len() || weights.is_some_and(|w| w.len() != targets.len()) {
+            panic!("cannot compute errors of vectors of different length!");
+        }
+        let weight = |i: usize| weights.map_or(1.0, |w| w[i]);
+        let total: Float = (0..targets.len()).map(&weight).sum();
+        match *self {
+            Binary::Accuracy(threshold) => {
+                let wrong: Float = (0..targets.len())
+                    .filter(|&i| predicted(outputs[i], threshold) != Some(is_positive(targets[i])))
+                    .map(&weight)
+                    .sum();
+                wrong / total
+            }
+            Binary::F1(threshold) => {
+                let (mut tp, mut fp, mut fn_) = (0.0, 0.0, 0.0);
+                for i in 0..targets.len() {
+                    match (predicted(outputs[i], threshold), is_positive(targets[i])) {
+                        (Some(true), true) => tp += weight(i),
+                        (Some(true), false) | (None, false) => fp += weight(i),
+                        (Some(false), true) | (None, true) => fn_ += weight(i),
+                        (Some(false), false) => {}
+                    }
+                }
+                if tp + fp + fn_ == 0.0 {
+                    // no positives at all, and none predicted.
+                    0.0
+                } else {
+                    1.0 - 2.0 * tp / (2.0 * tp + fp + fn_)
+                }
+            }
+            Binary::LogLoss => {
+                let loss: Float = (0..targets.len())
+                    .map(|i| {
+                        let p = logistic(outputs[i])
+                            .clamp(PROBABILITY_LIMIT, 1.0 - PROBABILITY_LIMIT);
+                        let y = targets[i];
+                        -weight(i) * (y * p.ln() + (1.0 - y) * (1.0 - p).ln())
+                    })
+                    .sum();
+                loss / total
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        match *self {
+            Binary::Accuracy(_) => "1 - accuracy",
+            Binary::F1(_) => "1 - f1",
+            Binary::LogLoss => "log loss",
+        }
+    }
+}
+
+fn is_positive(target: Float) -> bool {
+    target > 0.5
+}
+
+/// Returns whether `output` is of class `1` w.r.t. `threshold`, or `None` if it is not
+/// finite: such outputs are misclassified whatever the target.
+fn predicted(output: Float, threshold: Float) -> Option<bool> {
+    if output.is_finite() {
+        Some(output > threshold)
+    } else {
+        None
+    }
+}
+
+/// Returns the probability of class `1` for each output, i.e. its logistic function.
+pub fn probabilities(outputs: &[Float]) -> Vec<Float> {
+    outputs.iter().map(|o| logistic(*o)).collect()
+}
+
+/// Returns the class (`0` or `1`) of each output, w.r.t. `threshold`.
+pub fn classify(outputs: &[Float], threshold: Float) -> Vec<usize> {
+    outputs.iter().map(|o| if *o > threshold { 1 } else { 0 }).collect()
+}
+
+/// Returns the fraction of `predicted` classes equal to the class indices in `targets`.
+pub fn accuracy(predicted: &[usize], targets: &[Float]) -> Float {
+    if predicted.len() != targets.len() {
+        panic!("cannot compare vectors of different length!");
+    }
+    let right = predicted.iter().zip(targets).filter(|&(p, t)| *p == *t as usize).count();
+    right as Float / targets.len() as Float
+}
+
+/// A multi-class classifier made of one binary classifier per class.
+#[derive(Debug, Clone)]
+pub struct OneVsRest {
+    /// The classifier of each class, whose output is its confidence.
+    models: Vec<Individual>,
+}
+
+impl OneVsRest {
+    /// Evolves one classifier per class of `data` (whose targets are class indices):
+    /// `gp` returns the `GP` to evolve for `gens` generations out of the one-vs-rest
+    /// `Data` of each class, of which the fittest individual is kept.
+    ///
+    /// Classifiers must have a tree, i.e. `gp` must not use `Variation::GeometricSemantic`.
+    pub fn evolve<F>(data: &Data, gens: usize, gp: F) -> OneVsRest
+        where F: Fn(Data) -> GP
+    {
+        let n_classes = match data.classes() {
+            Some(classes) => classes.len(),
+            None => data.train_targets().iter().fold(0, |n, t| n.max(*t as usize + 1)),
+        };
+        let models = (0..n_classes)
+            .map(|class| {
+                let mut gp = gp(data.one_vs_rest(class));
+                gp.init_new_pop();
+                gp.evolve(gens);
+                gp.pop().get_first().clone()
+            })
+            .collect();
+        OneVsRest { models }
+    }
+
+    /// Returns a classifier out of the given per-class classifiers.
+    pub fn from_models(models: Vec<Individual>) -> OneVsRest {
+        OneVsRest { models }
+    }
+
+    pub fn models(&self) -> &[Individual] {
+        &self.models
+    }
+
+    /// Returns the probability of each class according to its own classifier,
+    /// indexed as `[class][instance]`. They need not sum up to one.
+    pub fn scores(&self, df: &Matrix) -> Vec<Vec<Float>> {
+        self.models.iter().map(|m| probabilities(&m.predict(df))).collect()
+    }
+
+    /// Returns the predicted class of each instance of `df`.
+    pub fn predict(&self, df: &Matrix) -> Vec<usize> {
+        let scores = self.scores(df);
+        (0..df.rows())
+            .map(|i| {
+                (0..scores.len())
+                    .max_by(|&a, &b| scores[a][i].total_cmp(&scores[b][i]))
+                    .expect("@OneVsRest::predict() No classes.")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::node::Node;
+
+    fn close(a: Float, b: Float) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn binary_errors_of_known_predictions() {
+        let outputs = [2.0, -1.0, 0.5, -3.0];
+        let targets = [1.0, 1.0, 0.0, 0.0];
+        // predicted classes 1, 0, 1, 0 with threshold 0: one true positive, one false
+        // negative and one false positive.
+        assert!(close(Binary::Accuracy(0.0).error(&outputs, &targets, None), 0.5));
+        assert!(close(Binary::F1(0.0).error(&outputs, &targets, None), 0.5));
+        // with threshold 1, only the first is positive: F1 = 2 / 3.
+        assert!(close(Binary::F1(1.0).error(&outputs, &targets, None), 1.0 / 3.0));
+        assert!(close(Binary::Accuracy(0.0).error(&outputs, &targets, Some(&[3.0, 1.0, 0.0, 0.0])),
+                      0.25));
+        assert_eq!(classify(&outputs, 0.0), vec![1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn non_finite_outputs_are_misclassified() {
+        let targets = [1.0, 0.0, 1.0, 0.0];
+        let outputs = [Float::INFINITY, Float::NEG_INFINITY, Float::NAN, Float::NAN];
+        assert_eq!(Binary::Accuracy(0.0).error(&outputs, &targets, None), 1.0);
+        assert_eq!(Binary::F1(0.0).error(&outputs, &targets, None), 1.0);
+        // one right output among them: one true positive, one false negative and two
+        // false positives.
+        let outputs = [1.0, Float::NAN, Float::INFINITY, Float::NAN];
+        assert!(close(Binary::Accuracy(0.0).error(&outputs, &targets, None), 0.75));
+        assert!(close(Binary::F1(0.0).error(&outputs, &targets, None), 1.0 - 2.0 / 5.0));
+    }
+
+    #[test]
+    fn log_loss_rewards_confident_right_outputs() {
+        let targets = [1.0, 0.0];
+        let undecided = Binary::LogLoss.error(&[0.0, 0.0], &targets, None);
+        assert!(close(undecided, (2.0 as Float).ln()));
+        assert!(Binary::LogLoss.error(&[5.0, -5.0], &targets, None) < undecided);
+        assert!(Binary::LogLoss.error(&[-5.0, 5.0], &targets, None) > undecided);
+        // wrong outputs beyond the probability limit still give a finite loss.
+        assert!(Binary::LogLoss.error(&[-1e3, 1e3], &targets, None).is_finite());
+    }
+
+    #[test]
+    fn one_vs_rest_predicts_the_most_confident_class() {
+        // the classifier of class j outputs input j.
+        let models = (0..3).map(|j| Individual::from_core(vec![Node::Input(j)])).collect();
+        let classifier = OneVsRest::from_models(models);
+        let df = Matrix::from_rows(&[vec![0.9, 0.1, 0.2], vec![-1.0, -2.0, 3.0]]);
+        assert_eq!(classifier.predict(&df), vec![0, 2]);
+        assert_eq!(accuracy(&classifier.predict(&df), &[0.0, 1.0]), 0.5);
+    }
+
+    #[test]
+    fn one_vs_rest_data_has_binary_targets() {
+        let inputs = Matrix::from_rows(&[vec![0.0], vec![1.0], vec![2.0]]);
+        let data = Data::from_matrices(inputs.clone(), vec![0.0, 2.0, 1.0], inputs, vec![2.0; 3]);
+        let binary = data.one_vs_rest(2);
+        assert_eq!(binary.train_targets(), &[0.0, 1.0, 0.0]);
+        assert_eq!(binary.test_targets(), &[1.0; 3]);
+    }
+}
@@ -0,0 +1,333 @@
+//! Local search over the constants of a program (Levenberg-Marquardt).
+//!
+//! `Node::get_random_const()` only draws from a handful of values in [-1, 1], which makes
+//! it hard for GP to fit scale and offset. Here the `Node::Constant` values of a program
+//! are tuned to minimise the squared error on training data, with the Jacobian of the
+//! outputs w.r.t. the constants obtained by forward-mode differentiation of the tree.
+
+use core::data::Data;
+use core::fitness::Fitness;
+use core::individual::Individual;
+use core::node::Node;
+use core::utils::{PROTECTED_DIVISION_LIMIT, solve, widen};
+use core::Float;
+use core::matrix::Matrix;
+
+/// Settings to apply constant optimisation along a `GP` run.
+#[derive(Debug, Clone, Copy)]
+pub struct ConstantOptimization {
+    /// Optimise every `every` generations.
+    pub every: usize,
+    /// Fraction of the population, starting from the fittest, that is optimised.
+    pub fraction: f32,
+    /// Maximum number of Levenberg-Marquardt iterations per individual.
+    pub iterations: usize,
+}
+
+/// Returns a copy of `ind` whose constants were tuned by at most `max_iterations`
+/// Levenberg-Marquardt iterations on the training data of `data`, and which is
+/// evaluated by `fitness`.
+///
+/// Only steps that improve the (weighted, if `data` has weights) squared error are
+/// taken, so the squared error of the returned `Individual` is never higher than that
+/// of `ind`; under other fitness functions its error may be. Individuals without a `core`
+/// (i.e. evolved with `Variation::GeometricSemantic`) or without constants are
+/// returned as they are.
+///
+/// If `ind` was evaluated with linear scaling, the squared error is that of its scaled
+/// outputs: the intercept and slope are tuned along with the constants, starting from
+/// those of `ind`, and the returned `Individual` is evaluated with linear scaling too
+/// (see `Individual::evaluate_scaled()`).
+pub fn optimize_constants(ind: &Individual,
+                          data: &Data,
+                          fitness: &dyn Fitness,
+                          max_iterations: usize)
+                          -> Individual {
+    let mut core = ind.clone_core();
+    let mut theta: Vec<f64> = constants(&core).into_iter().map(widen).collect();
+    if theta.is_empty() {
+        return ind.clone();
+    }
+    let targets = data.train_targets();
+    let weights = data.train_weights();
+    // under linear scaling, the intercept and slope follow the constants in `theta`.
+    let n_consts = theta.len();
+    if let Some((a, b)) = ind.scaling() {
+        theta.extend_from_slice(&[widen(a), widen(b)]);
+    }
+    let model = |core: &[Node], theta: &[f64]| {
+        let (outputs, jacobian) = forward(core, data.train());
+        match ind.scaling() {
+            Some(_) => scaled(outputs, jacobian, theta[n_consts], theta[n_consts + 1]),
+            None => (outputs, jacobian),
+        }
+    };
+
+    let (mut outputs, mut jacobian) = model(&core, &theta);
+    let mut sse = squared_error(&outputs, targets, weights);
+    let mut lambda = 1e-3;
+
+    for _ in 0..max_iterations {
+        let (a, g) = normal_equations(&jacobian, &outputs, targets, weights);
+        let mut improved = false;
+        while lambda < 1e10 {
+            let mut damped = a.clone();
+            for (k, row) in damped.iter_mut().enumerate() {
+                row[k] += lambda * (a[k][k] + 1e-12);
+            }
+            let step = match solve(damped, g.iter().map(|v| -v).collect()) {
+                Some(step) => step,
+                None => {
+                    lambda *= 10.0;
+                    continue;
+                }
+            };
+            let candidate: Vec<f64> = theta.iter().zip(&step).map(|(t, s)| t + s).collect();
+            set_constants(&mut core, &candidate);
+            let (c_outputs, c_jacobian) = model(&core, &candidate);
+            let c_sse = squared_error(&c_outputs, targets, weights);
+            if c_sse.is_finite() && c_sse < sse {
+                improved = (sse - c_sse) > 1e-12 * sse;
+                theta = candidate;
+                outputs = c_outputs;
+                jacobian = c_jacobian;
+                sse = c_sse;
+                lambda /= 10.0;
+                break;
+            }
+            lambda *= 10.0;
+        }
+        set_constants(&mut core, &theta);
+        if !improved {
+            break;
+        }
+    }
+
+    let mut optimized = Individual::from_core(core);
+    optimized.compute_semantics(data);
+    if ind.scaling().is_some() {
+        optimized.evaluate_scaled(data, fitness);
+    } else {
+        optimized.evaluate(data, fitness);
+    }
+    optimized
+}
+
+/// Returns the values of the constants of a program, in prefix order.
+fn constants(core: &[Node]) -> Vec<Float> {
+    core.iter()
+        .filter_map(|n| match *n {
+                        Node::Constant(val) => Some(val),
+                        _ => None,
+                    })
+        .collect()
+}
+
+/// Overwrites the constants of a program, in prefix order.
+fn set_constants(core: &mut [Node], theta: &[f64]) {
+    let mut k = 0;
+    for node in core.iter_mut() {
+        if let Node::Constant(_) = *node {
+            *node = Node::Constant(theta[k] as Float);
+            k += 1;
+        }
+    }
+}
+
+/// Returns the outputs of a program on `df` along with their partial derivatives
+/// w.r.t. each constant (indexed as `[constant][instance]`).
+fn forward(core: &[Node], df: &Matrix) -> (Vec<Float>, Vec<Vec<Float>>) {
+    let n_consts = core.iter()
+        .filter(|n| matches!(**n, Node::Constant(_)))
+        .count();
+    inner_forward(core, &mut 0, &mut 0, n_consts, df)
+}
+
+/// Recursive component of `forward()`. `idx` walks the program and `const_idx` counts the
+/// constants seen so far.
+fn inner_forward(core: &[Node],
+                 idx: &mut usize,
+                 const_idx: &mut usize,
+                 n_consts: usize,
+                 df: &Matrix)
+                 -> (Vec<Float>, Vec<Vec<Float>>) {
+    let n = df.rows();
+    let node = &core[*idx];
+    match *node {
+        Node::Constant(val) => {
+            let mut partials = vec![vec![0.0 as Float; n]; n_consts];
+            partials[*const_idx] = vec![1.0 as Float; n];
+            *const_idx += 1;
+            (vec![val; n], partials)
+        }
+        Node::Input(j) => (df.column(j).to_vec(), vec![vec![0.0 as Float; n]; n_consts]),
+        _ => {
+            let mut args = vec![];
+            for _child_node in 0..node.arity() {
+                *idx += 1;
+                args.push(inner_forward(core, idx, const_idx, n_consts, df));
+            }
+            let values = node.op(args.iter().map(|a| a.0.to_vec()).collect());
+            let partials = (0..n_consts)
+                .map(|k| chain_rule(node, &args, &values, k))
+                .collect();
+            (values, partials)
+        }
+    }
+}
+
+/// Returns the outputs `a + b * outputs` of a linearly scaled program, along with their
+/// partial derivatives w.r.t. each constant, then `a` and `b`.
+fn scaled(outputs: Vec<Float>,
+          mut jacobian: Vec<Vec<Float>>,
+          a: f64,
+          b: f64)
+          -> (Vec<Float>, Vec<Vec<Float>>) {
+    let (a, b) = (a as Float, b as Float);
+    for partials in &mut jacobian {
+        partials.iter_mut().for_each(|p| *p *= b);
+    }
+    jacobian.push(vec![1.0; outputs.len()]);
+    let scaled = outputs.iter().map(|o| a + b * o).collect();
+    jacobian.push(outputs);
+    (scaled, jacobian)
+}
+
+/// Returns the partial derivative of `node` w.r.t. the `k`-th constant,
+/// given the outputs and partials of its arguments and its own outputs.
+fn chain_rule(node: &Node,
+              args: &[(Vec<Float>, Vec<Vec<Float>>)],
+              values: &[Float],
+              k: usize)
+              -> Vec<Float> {
+    let a = &args[0];
+    match *node {
+        Node::Addition => a.1[k].iter().zip(&args[1].1[k]).map(|(da, db)| da + db).collect(),
+        Node::Subtraction => a.1[k].iter().zip(&args[1].1[k]).map(|(da, db)| da - db).collect(),
+        Node::Multiplication => {
+            let b = &args[1];
+            (0..values.len())
+                .map(|i| a.1[k][i] * b.0[i] + a.0[i] * b.1[k][i])
+                .collect()
+        }
+        Node::Division => {
+            let b = &args[1];
+            (0..values.len())
+                .map(|i| if b.0[i].abs() > PROTECTED_DIVISION_LIMIT {
+                         (a.1[k][i] * b.0[i] - a.0[i] * b.1[k][i]) / (b.0[i] * b.0[i])
+                     } else {
+                         a.1[k][i]
+                     })
+                .collect()
+        }
+        Node::Cosine => (0..values.len()).map(|i| -a.0[i].sin() * a.1[k][i]).collect(),
+        Node::LogFunction => {
+            (0..values.len())
+                .map(|i| values[i] * (1.0 - values[i]) * a.1[k][i])
+                .collect()
+        }
+        _ => panic!("@constant_optimization::chain_rule() Called on a non-functional node."),
+    }
+}
+
+/// Sum of (weighted) squared errors, accumulated in `f64`.
+fn squared_error(outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> f64 {
+    (0..outputs.len())
+        .map(|i| weight(weights, i) * (widen(outputs[i]) - widen(targets[i])).powi(2))
+        .sum()
+}
+
+/// Returns `J^T W J` and `J^T W r`, where `r` are the residuals of `outputs` against
+/// `targets` and `W` the diagonal matrix of `weights` (the identity if `None`).
+fn normal_equations(jacobian: &[Vec<Float>],
+                    outputs: &[Float],
+                    targets: &[Float],
+                    weights: Option<&[Float]>)
+                    -> (Vec<Vec<f64>>, Vec<f64>) {
+    let p = jacobian.len();
+    let mut a = vec![vec![0f64; p]; p];
+    let mut g = vec![0f64; p];
+    for i in 0..outputs.len() {
+        let w = weight(weights, i);
+        let r = widen(outputs[i]) - widen(targets[i]);
+        for k in 0..p {
+            let jk = w * widen(jacobian[k][i]);
+            g[k] += jk * r;
+            for l in 0..p {
+                a[k][l] += jk * widen(jacobian[l][i]);
+            }
+        }
+    }
+    (a, g)
+}
+
+/// Weight of instance `i`, `1.0` without weights.
+fn weight(weights: Option<&[Float]>, i: usize) -> f64 {
+    weights.map_or(1.0, |w| widen(w[i]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fitness::Metric;
+    use core::gp::GP;
+
+    #[test]
+    fn constants_reach_the_least_squares_fit() {
+        use core::node::Node::*;
+        let data = Data::new("yacht");
+        // c0 * x5 + c1, starting from 1 * x5 + 0.
+        let mut ind = Individual::from_core(vec![Addition,
+                                                 Multiplication,
+                                                 Constant(1.0),
+                                                 Input(5),
+                                                 Constant(0.0)]);
+        ind.compute_semantics(&data);
+        ind.evaluate(&data, &Metric::Rmse);
+        let optimized = optimize_constants(&ind, &data, &Metric::Rmse, 20);
+        assert!(optimized.train().unwrap() < ind.train().unwrap());
+
+        // closed-form simple linear regression of the targets on x5.
+        let (xs, ys) = (data.train().column(5), data.train_targets());
+        let n = xs.len() as f64;
+        let mean_x = xs.iter().map(|x| widen(*x)).sum::<f64>() / n;
+        let mean_y = ys.iter().map(|y| widen(*y)).sum::<f64>() / n;
+        let cov: f64 =
+            xs.iter().zip(ys).map(|(x, y)| (widen(*x) - mean_x) * (widen(*y) - mean_y)).sum();
+        let var: f64 = xs.iter().map(|x| (widen(*x) - mean_x).powi(2)).sum();
+        let slope = cov / var;
+        let intercept = mean_y - slope * mean_x;
+        let fitted = constants(optimized.core());
+        assert!((widen(fitted[0]) - slope).abs() < 1e-2 * slope.abs());
+        assert!((widen(fitted[1]) - intercept).abs() < 1e-2 * intercept.abs());
+    }
+
+    #[test]
+    fn constants_are_fitted_under_linear_scaling() {
+        use core::node::Node::*;
+        let data = Data::new("yacht");
+        // a + b * cos(c0 * x5), whose constant cannot be fitted without the scaling.
+        let mut ind = Individual::from_core(vec![Cosine, Multiplication, Constant(1.0), Input(5)]);
+        ind.compute_semantics(&data);
+        ind.evaluate_scaled(&data, &Metric::Rmse);
+
+        let optimized = optimize_constants(&ind, &data, &Metric::Rmse, 50);
+        assert!(optimized.scaling().is_some());
+        assert!(optimized.train() < ind.train());
+        let mut reevaluated = optimized.clone();
+        reevaluated.evaluate_scaled(&data, &Metric::Rmse);
+        assert_eq!(reevaluated.train(), optimized.train());
+    }
+
+    #[test]
+    #[should_panic(expected = "`every` must be at least 1")]
+    fn optimising_every_zero_generations_is_rejected() {
+        GP::new_gp(Data::new("yacht")).set_constant_optimization(0, 0.1, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "`fraction` must be in (0, 1]")]
+    fn optimising_an_empty_fraction_is_rejected() {
+        GP::new_gp(Data::new("yacht")).set_constant_optimization(1, 0.0, 5);
+    }
+}
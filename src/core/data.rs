@@ -2,93 +2,339 @@ use std::io::BufReader;
 use std::io::BufRead; // a trait of BufReader necessary for lines() method
 use std::fs::File;
 use std::io::Lines;
+use std::ops::Range;
+use rand::Rng;
+use core::Float;
+use core::matrix::Matrix;
 
 /// This struct assumes ONE! target output. Multiobjective optimization is not yet a feature.
-#[derive(Debug, Clone)] // copy is to allow non-consumption when initializing multiple gp's...
+#[derive(Debug, Clone)] // cloning shares the matrices, e.g. when initializing multiple gp's...
 pub struct Data {
-    // NOTE! Outputs to be predicted is assumed to be the last column!
-    dimensions: usize,
-    train: Vec<Vec<f32>>,
-    test: Vec<Vec<f32>>,
-    // to [j][i], e.g. test[0] gets the first variable for all instances ;)
+    // Inputs are column-major: train.column(0) gets the first variable for all instances ;)
+    train: Matrix,
+    test: Matrix,
+    // Outputs to be predicted, as single-column matrices.
+    train_targets: Matrix,
+    test_targets: Matrix,
+    // Optional weights of instances in errors (see `core::fitness`), as the targets.
+    train_weights: Option<Matrix>,
+    test_weights: Option<Matrix>,
+    // Names of the classes when targets are class indices (see `new_classification()`).
+    classes: Option<Vec<String>>,
 }
 
 impl Data {
-    pub fn train(&self) -> &Vec<Vec<f32>> {
+    /// Returns the training inputs, one column per variable.
+    pub fn train(&self) -> &Matrix {
         &self.train
     }
-    pub fn test(&self) -> &Vec<Vec<f32>> {
+    /// Returns the test inputs, one column per variable.
+    pub fn test(&self) -> &Matrix {
         &self.test
     }
     pub fn dims(&self) -> usize {
-        self.dimensions
+        self.train.cols()
     }
 
-    pub fn train_targets(&self) -> &Vec<f32> {
-        &self.train[self.train.len() - 1]
+    /// Returns whether both hold the very same training and test instances (see
+    /// `Matrix::is_same()`), as clones of one another do.
+    pub fn is_same(&self, other: &Data) -> bool {
+        self.train.is_same(&other.train) && self.test.is_same(&other.test) &&
+        self.train_targets.is_same(&other.train_targets) &&
+        self.test_targets.is_same(&other.test_targets)
     }
-    pub fn test_targets(&self) -> &Vec<f32> {
-        &self.test[self.test.len() - 1]
+
+    pub fn train_targets(&self) -> &[Float] {
+        self.train_targets.column(0)
+    }
+    pub fn test_targets(&self) -> &[Float] {
+        self.test_targets.column(0)
+    }
+
+    /// Returns the weights of training instances, if any.
+    pub fn train_weights(&self) -> Option<&[Float]> {
+        self.train_weights.as_ref().map(|w| w.column(0))
+    }
+    /// Returns the weights of test instances, if any.
+    pub fn test_weights(&self) -> Option<&[Float]> {
+        self.test_weights.as_ref().map(|w| w.column(0))
+    }
+
+    /// Returns this `Data` with weights for the errors of training and test instances
+    /// (see `core::fitness`). Weights must not be negative, nor all zero.
+    pub fn with_weights(mut self, train_weights: Vec<Float>, test_weights: Vec<Float>) -> Data {
+        if train_weights.len() != self.train.rows() || test_weights.len() != self.test.rows() {
+            panic!("@Data::with_weights() Number of weights differs from number of instances.");
+        }
+        let valid = |w: &[Float]| w.iter().all(|v| *v >= 0.0) && w.iter().any(|v| *v > 0.0);
+        if !valid(&train_weights) || !valid(&test_weights) {
+            panic!("@Data::with_weights() Weights must be non-negative and not all zero.");
+        }
+        let (train_rows, test_rows) = (train_weights.len(), test_weights.len());
+        self.train_weights = Some(Matrix::from_buffer(train_weights, train_rows, 1));
+        self.test_weights = Some(Matrix::from_buffer(test_weights, test_rows, 1));
+        self
     }
 
+    /// Loads `datasets/<dataset>/train.txt` and `test.txt`, whitespace separated
+    /// with one instance per line and the output to be predicted in the last column.
     pub fn new(dataset: &'static str) -> Data {
         let train_d = "datasets/".to_string() + dataset + "/train.txt";
         let test_d = "datasets/".to_string() + dataset + "/test.txt";
 
-        let train = Data::new_df(train_d);
-        // due to transposition, each row is a variable instead of an instance.
-        let dims = train.len() - 1;
-        let test = Data::new_df(test_d);
+        let (train, train_targets) = Data::split_targets(Data::new_df(train_d));
+        let (test, test_targets) = Data::split_targets(Data::new_df(test_d));
+        Data {
+            train,
+            test,
+            train_targets,
+            test_targets,
+            train_weights: None,
+            test_weights: None,
+            classes: None,
+        }
+    }
+
+    /// Loads a classification dataset laid out as for `new()`, except that the last
+    /// column holds class labels, which may be any strings. Labels are mapped to
+    /// indices into the sorted labels of both sets (see `classes()`), which become the
+    /// targets. Labels are sorted by value if they are all numbers (`"2"` before `"10"`),
+    /// and lexicographically otherwise.
+    pub fn new_classification(dataset: &'static str) -> Data {
+        let train_d = "datasets/".to_string() + dataset + "/train.txt";
+        let test_d = "datasets/".to_string() + dataset + "/test.txt";
+
+        let (train, train_labels) = Data::new_labelled_df(train_d);
+        let (test, test_labels) = Data::new_labelled_df(test_d);
+        let classes = sorted_classes(&train_labels, &test_labels);
+        let index = |labels: Vec<String>| -> Vec<Float> {
+            labels.iter()
+                .map(|l| classes.iter().position(|c| c == l).unwrap() as Float)
+                .collect()
+        };
+        let (train_targets, test_targets) = (index(train_labels), index(test_labels));
+        let mut data = Data::from_matrices(train, train_targets, test, test_targets);
+        data.classes = Some(classes);
+        data
+    }
+
+    /// Returns the names of the classes, indexed by target, if loaded with
+    /// `new_classification()`.
+    pub fn classes(&self) -> Option<&[String]> {
+        self.classes.as_deref()
+    }
+
+    /// Returns this `Data` with other targets for the same instances.
+    /// Class names, if any, are dropped.
+    pub fn with_targets(mut self, train_targets: Vec<Float>, test_targets: Vec<Float>) -> Data {
+        if train_targets.len() != self.train.rows() || test_targets.len() != self.test.rows() {
+            panic!("@Data::with_targets() Number of targets differs from number of instances.");
+        }
+        let (train_rows, test_rows) = (train_targets.len(), test_targets.len());
+        self.train_targets = Matrix::from_buffer(train_targets, train_rows, 1);
+        self.test_targets = Matrix::from_buffer(test_targets, test_rows, 1);
+        self.classes = None;
+        self
+    }
+
+    /// Returns a binary classification `Data` whose targets are `1.0` for instances of
+    /// class `class` and `0.0` for the others, sharing the inputs.
+    pub fn one_vs_rest(&self, class: usize) -> Data {
+        let binary = |targets: &[Float]| -> Vec<Float> {
+            targets.iter()
+                .map(|t| if *t as usize == class { 1.0 } else { 0.0 })
+                .collect()
+        };
+        let (train_targets, test_targets) = (binary(self.train_targets()),
+                                             binary(self.test_targets()));
+        self.clone().with_targets(train_targets, test_targets)
+    }
+
+    /// Returns a `Data` from inputs and targets already in memory.
+    pub fn from_matrices(train: Matrix,
+                         train_targets: Vec<Float>,
+                         test: Matrix,
+                         test_targets: Vec<Float>)
+                         -> Data {
+        if train.rows() != train_targets.len() || test.rows() != test_targets.len() {
+            panic!("@Data::from_matrices() Number of targets differs from number of instances.");
+        }
+        if train.cols() != test.cols() {
+            panic!("@Data::from_matrices() Training and test inputs differ in dimensions.");
+        }
+        let train_rows = train_targets.len();
+        let test_rows = test_targets.len();
+        Data {
+            train,
+            test,
+            train_targets: Matrix::from_buffer(train_targets, train_rows, 1),
+            test_targets: Matrix::from_buffer(test_targets, test_rows, 1),
+            train_weights: None,
+            test_weights: None,
+            classes: None,
+        }
+    }
+
+    /// Returns a `Data` whose training set is made of the training instances in `range`,
+    /// without copying. The test set is the same.
+    pub fn train_rows(&self, range: Range<usize>) -> Data {
+        Data {
+            train: self.train.row_range(range.clone()),
+            train_targets: self.train_targets.row_range(range.clone()),
+            train_weights: self.train_weights.as_ref().map(|w| w.row_range(range)),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a `Data` whose training set is made of the given training instances,
+    /// possibly repeated (e.g. a bootstrap sample), copied. The test set is the same.
+    pub fn train_sample(&self, rows: &[usize]) -> Data {
         Data {
-            dimensions: dims,
-            train: train,
-            test: test,
+            train: self.train.select_rows(rows),
+            train_targets: self.train_targets.select_rows(rows),
+            train_weights: self.train_weights.as_ref().map(|w| w.select_rows(rows)),
+            ..self.clone()
         }
     }
 
-    fn new_df(filename: String) -> Vec<Vec<f32>> {
+    /// Returns a `Data` whose training set is a bootstrap sample of the training set,
+    /// i.e. as many instances drawn with replacement, copied. The test set is the same.
+    pub fn bootstrap<R: Rng>(&self, rng: &mut R) -> Data {
+        let n = self.train.rows();
+        let rows: Vec<usize> = (0..n).map(|_| rng.gen_range(0, n)).collect();
+        self.train_sample(&rows)
+    }
+
+    /// Returns `k` `Data` whose training sets are the `k` contiguous blocks of training
+    /// instances, without copying. The test set is the same.
+    pub fn shards(&self, k: usize) -> Vec<Data> {
+        let n = self.train.rows();
+        if k == 0 || k > n {
+            panic!("@Data::shards() Cannot make {} shards out of {} instances.", k, n);
+        }
+        (0..k).map(|i| self.train_rows((i * n / k)..((i + 1) * n / k))).collect()
+    }
+
+    /// Returns a `Data` with only the given input variables, in that order, copied.
+    /// Input `i` of the new `Data` is input `features[i]` of this one.
+    pub fn select_features(&self, features: &[usize]) -> Data {
+        if features.is_empty() || features.iter().any(|&f| f >= self.dims()) {
+            panic!("@Data::select_features() Features must be some of the {} inputs.",
+                   self.dims());
+        }
+        Data {
+            train: self.train.select_columns(features),
+            test: self.test.select_columns(features),
+            ..self.clone()
+        }
+    }
+
+    /// Returns `k` folds of the training set for cross-validation. Fold `i` has the
+    /// `i`-th of `k` contiguous blocks of training instances as test set, without
+    /// copying, and the remaining instances as training set, copied.
+    pub fn folds(&self, k: usize) -> Vec<Data> {
+        let n = self.train.rows();
+        if k < 2 || k > n {
+            panic!("@Data::folds() Cannot make {} folds out of {} instances.", k, n);
+        }
+        (0..k)
+            .map(|i| {
+                let block = (i * n / k)..((i + 1) * n / k);
+                let rest: Vec<usize> = (0..n).filter(|r| !block.contains(r)).collect();
+                Data {
+                    train: self.train.select_rows(&rest),
+                    train_targets: self.train_targets.select_rows(&rest),
+                    test: self.train.row_range(block.clone()),
+                    test_targets: self.train_targets.row_range(block.clone()),
+                    train_weights: self.train_weights.as_ref().map(|w| w.select_rows(&rest)),
+                    test_weights: self.train_weights.as_ref().map(|w| w.row_range(block)),
+                    classes: self.classes.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Splits a matrix of inputs followed by one column of targets into both.
+    fn split_targets(df: Matrix) -> (Matrix, Matrix) {
+        let dims = df.cols() - 1;
+        (df.column_range(0..dims), df.column_range(dims..dims + 1))
+    }
+
+    fn new_df(filename: String) -> Matrix {
         let itr = Data::get_iterator(filename.as_str());
-        let df = Data::transpose_array(Data::fill_array(itr));
-        df
+        Matrix::from_rows(&Data::fill_array(itr))
     }
 
-    fn get_iterator(filepath: &str) -> Lines<BufReader<File>> {
-        let f = File::open(filepath);
-        let file;
-        match f {
-            Ok(k) => file = k,
-            Err(e) => panic!(e),
+    /// Reads inputs and the labels in the last column.
+    fn new_labelled_df(filename: String) -> (Matrix, Vec<String>) {
+        let mut inputs: Vec<Vec<Float>> = vec![];
+        let mut labels = vec![];
+        for line in Data::get_iterator(filename.as_str()) {
+            let line = line.unwrap();
+            let mut vars: Vec<&str> = line.split_whitespace().collect();
+            match vars.pop() {
+                Some(label) => labels.push(label.to_string()),
+                None => continue,
+            }
+            inputs.push(vars.iter().map(|var| var.parse().unwrap()).collect());
         }
-        let file = BufReader::new(file);
-        let lines = file.lines();
-        lines
+        (Matrix::from_rows(&inputs), labels)
+    }
+
+    fn get_iterator(filepath: &str) -> Lines<BufReader<File>> {
+        let file = match File::open(filepath) {
+            Ok(k) => k,
+            Err(e) => panic!("{}", e),
+        };
+        BufReader::new(file).lines()
     }
 
-    fn fill_array(lines: Lines<BufReader<File>>) -> Vec<Vec<f32>> {
-        let mut array: Vec<Vec<f32>> = vec![];
+    fn fill_array(lines: Lines<BufReader<File>>) -> Vec<Vec<Float>> {
+        let mut array: Vec<Vec<Float>> = vec![];
         for line in lines {
-            let mut inst: Vec<f32> = vec![];
+            let mut inst: Vec<Float> = vec![];
             for var in line.unwrap().split_whitespace() {
-                let val: f32 = var.parse().unwrap();
+                let val: Float = var.parse().unwrap();
                 inst.push(val);
             }
             array.push(inst);
         }
-        let array = array;
         array
     }
+}
 
-    fn transpose_array(array: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
-        let mut result: Vec<Vec<f32>> = vec![];
-        for j in 0..array[0].len() {
-            // first collect column
-            let mut column = vec![];
-            for i in 0..array.len() {
-                column.push(array[i][j]);
-            }
-            // then push column as a row into result
-            result.push(column);
-        }
-        result
+/// Returns the distinct labels of both sets, sorted by value if they are all numbers
+/// and lexicographically otherwise.
+fn sorted_classes(train_labels: &[String], test_labels: &[String]) -> Vec<String> {
+    let mut classes: Vec<String> = train_labels.iter().chain(test_labels).cloned().collect();
+    classes.sort();
+    classes.dedup();
+    let values: Option<Vec<f64>> = classes.iter().map(|l| l.parse().ok()).collect();
+    if let Some(values) = values {
+        let mut by_value: Vec<(f64, String)> = values.into_iter().zip(classes).collect();
+        by_value.sort_by(|a, b| a.0.total_cmp(&b.0));
+        classes = by_value.into_iter().map(|(_, l)| l).collect();
+    }
+    classes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn numeric_labels_are_sorted_by_value() {
+        let classes = sorted_classes(&labels(&["10", "2", "1.5"]), &labels(&["2", "-3"]));
+        assert_eq!(classes, labels(&["-3", "1.5", "2", "10"]));
+    }
+
+    #[test]
+    fn other_labels_are_sorted_lexicographically() {
+        let classes = sorted_classes(&labels(&["virginica", "10", "setosa"]), &labels(&["2"]));
+        assert_eq!(classes, labels(&["10", "2", "setosa", "virginica"]));
     }
 }
@@ -0,0 +1,140 @@
+//! Symbolic differentiation of programs w.r.t. their inputs.
+//!
+//! The derivative of a program is itself a program over the same `Node` set, so that it
+//! can be evaluated, simplified and exported like any other `Individual`. Since there is
+//! no sine node, `-sin(a)` is written `cos(a + pi/2)`. Protected division `pdiv(n, d)`
+//! follows the quotient rule where `|d|` is above `PROTECTED_DIVISION_LIMIT`, and is
+//! `n'` elsewhere; the switch between both is built out of protected division itself.
+
+use std::f64::consts::FRAC_PI_2;
+use core::individual::Individual;
+use core::node::Node;
+use core::tree::Tree;
+use core::simplification::simplify_tree;
+use core::Float;
+use core::matrix::Matrix;
+
+/// Returns an `Individual` whose program is the derivative of the output of `ind`
+/// w.r.t. input `j`, i.e. `d(output)/d(x_j)`, simplified.
+///
+/// Linear scaling coefficients of `ind`, if any, are accounted for.
+/// The returned `Individual` has size and depth computed, but no semantics.
+pub fn derivative(ind: &Individual, j: usize) -> Individual {
+    if ind.core().is_empty() {
+        panic!("@differentiation::derivative() Cannot differentiate an Individual without a core.");
+    }
+    let mut d = derivative_tree(&Tree::from_prefix(ind.core()), j);
+    if let Some((_, slope)) = ind.scaling() {
+        d = mul(constant(slope), d);
+    }
+    Individual::from_core(simplify_tree(d).to_prefix())
+}
+
+/// Returns the (unsimplified) derivative of `tree` w.r.t. input `j`.
+pub fn derivative_tree(tree: &Tree, j: usize) -> Tree {
+    let c = &tree.children;
+    match tree.node {
+        Node::Constant(_) => constant(0.0),
+        Node::Input(i) => constant(if i == j { 1.0 } else { 0.0 }),
+        Node::Addition => add(derivative_tree(&c[0], j), derivative_tree(&c[1], j)),
+        Node::Subtraction => sub(derivative_tree(&c[0], j), derivative_tree(&c[1], j)),
+        Node::Multiplication => {
+            add(mul(derivative_tree(&c[0], j), c[1].clone()),
+                mul(c[0].clone(), derivative_tree(&c[1], j)))
+        }
+        Node::Division => {
+            let (n, d) = (&c[0], &c[1]);
+            let (dn, dd) = (derivative_tree(n, j), derivative_tree(d, j));
+            // pdiv(n', d) - pdiv(pdiv(n d', d), d) is the quotient rule where |d| is above
+            // the limit, and n' - n d' elsewhere, hence the correction below.
+            let n_dd = mul(n.clone(), dd);
+            let quotient_rule = sub(pdiv(dn, d.clone()),
+                                    pdiv(pdiv(n_dd.clone(), d.clone()), d.clone()));
+            add(quotient_rule, mul(protected_indicator(d), n_dd))
+        }
+        Node::Cosine => {
+            mul(cos(add(c[0].clone(), constant(FRAC_PI_2 as Float))),
+                derivative_tree(&c[0], j))
+        }
+        Node::LogFunction => {
+            let s = tree.clone();
+            mul(mul(s.clone(), sub(constant(1.0), s)), derivative_tree(&c[0], j))
+        }
+    }
+}
+
+/// Returns the derivative of the output of `ind` w.r.t. each input of `df`,
+/// evaluated on `df` (the inputs of `Data`), indexed as `[input][instance]`.
+pub fn gradient(ind: &Individual, df: &Matrix) -> Vec<Vec<Float>> {
+    (0..df.cols()).map(|j| derivative(ind, j).predict(df)).collect()
+}
+
+/// Returns a program that outputs exactly `1.0` where protected division replaces the
+/// denominator `d` by `1.0`, and `0.0` elsewhere.
+///
+/// `pdiv(d, d)` is `1.0` for a regular denominator and `d` otherwise, so that
+/// `e = 1 - pdiv(d, d)` is either `0.0` or close to `1.0`, and `pdiv(e, e)` is the indicator.
+fn protected_indicator(d: &Tree) -> Tree {
+    let e = sub(constant(1.0), pdiv(d.clone(), d.clone()));
+    pdiv(e.clone(), e)
+}
+
+fn constant(val: Float) -> Tree {
+    Tree::leaf(Node::Constant(val))
+}
+
+fn add(a: Tree, b: Tree) -> Tree {
+    Tree::branch(Node::Addition, vec![a, b])
+}
+
+fn sub(a: Tree, b: Tree) -> Tree {
+    Tree::branch(Node::Subtraction, vec![a, b])
+}
+
+fn mul(a: Tree, b: Tree) -> Tree {
+    Tree::branch(Node::Multiplication, vec![a, b])
+}
+
+fn pdiv(a: Tree, b: Tree) -> Tree {
+    Tree::branch(Node::Division, vec![a, b])
+}
+
+fn cos(a: Tree) -> Tree {
+    Tree::branch(Node::Cosine, vec![a])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::data::Data;
+
+    #[test]
+    fn gradient_matches_the_analytical_derivatives() {
+        use core::node::Node::*;
+        let data = Data::new("yacht");
+        // x0 * x0 + cos(x0 * x1) / (x1 + 2)
+        let ind = Individual::from_core(vec![Addition,
+                                             Multiplication,
+                                             Input(0),
+                                             Input(0),
+                                             Division,
+                                             Cosine,
+                                             Multiplication,
+                                             Input(0),
+                                             Input(1),
+                                             Addition,
+                                             Input(1),
+                                             Constant(2.0)]);
+        let grad = gradient(&ind, data.train());
+        assert_eq!(grad.len(), data.dims());
+        let inputs = data.train().column(0).iter().zip(data.train().column(1));
+        for (i, (&x0, &x1)) in inputs.enumerate() {
+            let d = x1 + 2.0;
+            let d0 = 2.0 * x0 - x1 * (x0 * x1).sin() / d;
+            let d1 = -x0 * (x0 * x1).sin() / d - (x0 * x1).cos() / (d * d);
+            assert!((grad[0][i] - d0).abs() < 1e-4, "{} != {}", grad[0][i], d0);
+            assert!((grad[1][i] - d1).abs() < 1e-4, "{} != {}", grad[1][i], d1);
+            assert!(grad[2..].iter().all(|g| g[i] == 0.0));
+        }
+    }
+}
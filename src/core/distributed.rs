@@ -0,0 +1,579 @@
+//! Islands distributed over processes or machines, connected over TCP.
+//!
+//! A `Coordinator` waits for a given number of `Worker`s, each evolving a `GP` island,
+//! then routes the migrants they send along a `Topology` and gathers the statistics
+//! they report. Workers evolve at their own pace, as in `Mgp::evolve_async()`: whenever
+//! their migration interval elapses they send emigrants and take in the immigrants
+//! received so far. A worker disconnecting mid-run, or before the start, is reported
+//! as such, and the others carry on.
+//!
+//! Each worker is written to by its own thread, out of a bounded outbox: a worker that
+//! does not keep up with its immigrants loses batches, as from a full mailbox in
+//! `Mgp::evolve_async()`, without holding up the others.
+//!
+//! Messages are lines of text. Migrants travel as programs in prefix notation, which are
+//! re-evaluated by the islands they join (see `GP::adopt()`):
+//!
+//! ```text
+//! coordinator -> worker   WELCOME <island>, then START once all workers are connected
+//! worker -> coordinator   MIGRANTS <count>, followed by one program per line
+//! coordinator -> worker   MIGRANTS <count>, followed by one program per line
+//! worker -> coordinator   STATS <generation> <train error> <test error> <size>
+//! worker -> coordinator   DONE
+//! ```
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::{thread_rng, XorShiftRng};
+use core::Float;
+use core::gp::GP;
+use core::individual::Individual;
+use core::multi_gp::{emigrants, immigrate, Emigration, Migrants, Replacement, Topology};
+use core::node::Node;
+use core::utils::{rng_from_seed, split_rng};
+
+/// State of the fittest individual of an island, as reported by its worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Generations evolved so far.
+    pub generation: usize,
+    pub train: Option<Float>,
+    pub test: Option<Float>,
+    pub size: usize,
+}
+
+/// What the coordinator heard from one island.
+#[derive(Debug, Clone)]
+pub struct IslandReport {
+    /// Statistics in the order they were reported.
+    pub stats: Vec<Stats>,
+    /// Whether the worker completed its run, rather than disconnecting mid-run.
+    pub finished: bool,
+}
+
+/// Batches of migrants waiting to be written to a worker, beyond which they are lost.
+const OUTBOX_CAPACITY: usize = 16;
+
+/// Routes migrants between `Worker`s connected over TCP.
+pub struct Coordinator {
+    listener: TcpListener,
+    topology: Topology,
+    accept_timeout: Option<Duration>,
+    rng: XorShiftRng,
+}
+
+impl Coordinator {
+    /// Listens on `addr`, e.g. `"127.0.0.1:0"` for any free port on localhost.
+    /// Workers are connected in a ring by default, and must all connect within
+    /// 60 seconds.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Coordinator> {
+        Ok(Coordinator {
+            listener: TcpListener::bind(addr)?,
+            topology: Topology::Ring,
+            accept_timeout: Some(Duration::from_secs(60)),
+            rng: split_rng(&mut thread_rng()),
+        })
+    }
+
+    /// Returns the address workers should connect to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Sets which islands send migrants to which. Islands are numbered in order of
+    /// connection.
+    pub fn set_topology(mut self, topology: Topology) -> Coordinator {
+        self.topology = topology;
+        self
+    }
+
+    /// Sets how long `run()` waits for all workers to connect. `None` waits forever.
+    pub fn set_accept_timeout(mut self, timeout: Option<Duration>) -> Coordinator {
+        self.accept_timeout = timeout;
+        self
+    }
+
+    /// Seeds the random number generator used by `Topology::Random`.
+    pub fn set_seed(mut self, seed: u64) -> Coordinator {
+        self.rng = rng_from_seed(seed);
+        self
+    }
+
+    /// Waits for `islands` workers to connect, starts them, and routes their migrants
+    /// until every one of them is done or disconnected. Returns a report per island.
+    ///
+    /// Fails with `io::ErrorKind::TimedOut` if not all workers connect within the
+    /// accept timeout, dropping those already connected.
+    pub fn run(&mut self, islands: usize) -> io::Result<Vec<IslandReport>> {
+        if !self.topology.fits(islands) {
+            panic!("@Coordinator::run() {:?} does not fit {} islands.", self.topology, islands);
+        }
+        // workers gone before the start are `None`, and reported as not finished.
+        let workers: Vec<Option<(TcpStream, TcpStream)>> = self.accept(islands)?
+            .into_iter()
+            .map(|mut stream| {
+                writeln!(stream, "START").and_then(|_| stream.try_clone()).ok().map(|w| (stream, w))
+            })
+            .collect();
+        // outboxes of the islands still listening, `None` once done or disconnected.
+        let mut outboxes = vec![];
+        let mut writers = vec![];
+        for worker in workers {
+            let (sender, outbox) = sync_channel::<String>(OUTBOX_CAPACITY);
+            let alive = worker.is_some();
+            outboxes.push(Mutex::new(if alive { Some(sender) } else { None }));
+            writers.push(worker.map(|(reader, writer)| (reader, writer, outbox)));
+        }
+        let (topology, rng) = (&self.topology, Mutex::new(&mut self.rng));
+        let reports = thread::scope(|scope| {
+            let handles: Vec<_> = writers.into_iter()
+                .enumerate()
+                .map(|(island, worker)| {
+                    let (outboxes, rng) = (&outboxes, &rng);
+                    scope.spawn(move || {
+                        let (reader, mut writer, outbox) = match worker {
+                            Some(worker) => worker,
+                            None => {
+                                return IslandReport {
+                                    stats: vec![],
+                                    finished: false,
+                                }
+                            }
+                        };
+                        // ends once the outbox is dropped, or the worker is gone.
+                        scope.spawn(move || for message in outbox {
+                            if writer.write_all(message.as_bytes()).is_err() {
+                                break;
+                            }
+                        });
+                        let report = listen(reader, |count, programs| {
+                            let destinations = {
+                                let mut rng = rng.lock().unwrap();
+                                topology.destinations(island, islands, &mut **rng)
+                            };
+                            let message = migrants_message(count, programs);
+                            for destination in destinations {
+                                let outbox = outboxes[destination].lock().unwrap().clone();
+                                if let Some(outbox) = outbox {
+                                    let _ = outbox.try_send(message.clone());
+                                }
+                            }
+                        });
+                        *outboxes[island].lock().unwrap() = None;
+                        report
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        Ok(reports)
+    }
+
+    /// Accepts `islands` workers within the accept timeout, welcoming each with its
+    /// number. Workers gone before being welcomed are not counted.
+    fn accept(&self, islands: usize) -> io::Result<Vec<TcpStream>> {
+        let deadline = self.accept_timeout.map(|t| Instant::now() + t);
+        self.listener.set_nonblocking(deadline.is_some())?;
+        let mut streams = vec![];
+        while streams.len() < islands {
+            match self.listener.accept() {
+                Ok((mut stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    if writeln!(stream, "WELCOME {}", streams.len()).is_ok() {
+                        streams.push(stream);
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if deadline.is_some_and(|d| Instant::now() >= d) {
+                        let message = format!("{} of {} workers connected", streams.len(), islands);
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, message));
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(streams)
+    }
+}
+
+/// Reads the messages of a worker until it is done or disconnected, passing its
+/// migrants (their number and programs) to `route`.
+fn listen<F>(stream: TcpStream, mut route: F) -> IslandReport
+    where F: FnMut(usize, &str)
+{
+    let mut report = IslandReport {
+        stats: vec![],
+        finished: false,
+    };
+    let mut lines = BufReader::new(stream).lines();
+    while let Some(Ok(line)) = lines.next() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["MIGRANTS", count] => {
+                let count = count.parse().unwrap_or(0);
+                let programs: Vec<String> =
+                    lines.by_ref().take(count).filter_map(Result::ok).collect();
+                if programs.len() < count {
+                    break;
+                }
+                route(count, &programs.join("\n"));
+            }
+            ["STATS", generation, train, test, size] => {
+                report.stats.push(Stats {
+                    generation: generation.parse().unwrap_or(0),
+                    train: train.parse().ok(),
+                    test: test.parse().ok(),
+                    size: size.parse().unwrap_or(0),
+                });
+            }
+            ["DONE"] => {
+                report.finished = true;
+                break;
+            }
+            _ => {}
+        }
+    }
+    report
+}
+
+/// Returns the message carrying `count` migrants, whose `programs` are one per line.
+fn migrants_message(count: usize, programs: &str) -> String {
+    if count == 0 {
+        "MIGRANTS 0\n".to_string()
+    } else {
+        format!("MIGRANTS {}\n{}\n", count, programs)
+    }
+}
+
+/// Evolves a `GP` island as part of a run routed by a `Coordinator`.
+pub struct Worker {
+    gp: GP,
+    stream: TcpStream,
+    /// Reads from `stream`, until handed over to the receiving thread by `run()`.
+    reader: Option<BufReader<TcpStream>>,
+    island: usize,
+    migrants: Migrants,
+    emigration: Emigration,
+    replacement: Replacement,
+    interval: usize,
+    reconstruction_limit: usize,
+    mailbox_capacity: usize,
+    rng: XorShiftRng,
+}
+
+impl Worker {
+    /// Connects to the `Coordinator` at `addr` to evolve `gp`. Defaults are as for
+    /// `Mgp`: 4 emigrants, the best, replacing the worst individuals every 30
+    /// generations.
+    pub fn connect<A: ToSocketAddrs>(addr: A, gp: GP) -> io::Result<Worker> {
+        let stream = TcpStream::connect(addr)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let island = match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            ["WELCOME", island] => island.parse().ok(),
+            _ => None,
+        };
+        let island = island.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "expected WELCOME from coordinator")
+        })?;
+        Ok(Worker {
+            gp,
+            stream,
+            reader: Some(reader),
+            island,
+            migrants: Migrants::Count(4),
+            emigration: Emigration::Best,
+            replacement: Replacement::Worst,
+            interval: 30,
+            reconstruction_limit: 10_000,
+            mailbox_capacity: 4,
+            rng: split_rng(&mut thread_rng()),
+        })
+    }
+
+    /// Sets how many individuals emigrate at each migration.
+    pub fn set_migrants(mut self, migrants: Migrants) -> Worker {
+        self.migrants = migrants;
+        self
+    }
+
+    /// Picks which individuals emigrate.
+    pub fn set_emigration(mut self, emigration: Emigration) -> Worker {
+        self.emigration = emigration;
+        self
+    }
+
+    /// Picks which residents immigrants replace.
+    pub fn set_replacement(mut self, replacement: Replacement) -> Worker {
+        self.replacement = replacement;
+        self
+    }
+
+    /// Sets the number of generations between migrations. `0` means no migration.
+    pub fn set_migration_interval(mut self, gens: usize) -> Worker {
+        self.interval = gens;
+        self
+    }
+
+    /// Sets the largest program, in nodes, sent for an emigrant without a tree (see
+    /// `Individual::reconstruct()`). Larger emigrants are not sent.
+    pub fn set_reconstruction_limit(mut self, nodes: usize) -> Worker {
+        self.reconstruction_limit = nodes;
+        self
+    }
+
+    /// Sets how many batches of immigrants wait to be taken in; further batches are lost.
+    pub fn set_mailbox_capacity(mut self, batches: usize) -> Worker {
+        if batches == 0 {
+            panic!("@Worker::set_mailbox_capacity() Mailboxes must hold at least one batch.");
+        }
+        self.mailbox_capacity = batches;
+        self
+    }
+
+    /// Seeds the random number generator used for migration.
+    pub fn set_seed(mut self, seed: u64) -> Worker {
+        self.rng = rng_from_seed(seed);
+        self
+    }
+
+    /// Returns the number of this island, given by the coordinator.
+    pub fn island(&self) -> usize {
+        self.island
+    }
+
+    pub fn gp(&self) -> &GP {
+        &self.gp
+    }
+
+    /// Waits for the coordinator to start the run, then evolves for `gens` generations,
+    /// migrating at every interval and reporting statistics then and at the end.
+    ///
+    /// Should the coordinator go away mid-run, the island evolves on in isolation.
+    /// A `Worker` runs only once.
+    pub fn run(&mut self, gens: usize) -> io::Result<()> {
+        let mut lines = match self.reader.take() {
+            Some(reader) => reader.lines(),
+            None => panic!("@Worker::run() The worker has already run."),
+        };
+        match lines.next() {
+            Some(Ok(ref line)) if line == "START" => {}
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "expected START from coordinator"))
+            }
+        }
+        if self.gp.pop().size() == 0 {
+            self.gp.init_new_pop();
+        }
+        let (mailbox_sender, mailbox) = sync_channel(self.mailbox_capacity);
+        let dims = self.gp.data().dims();
+        let receiver = thread::spawn(move || receive(lines, dims, mailbox_sender));
+        let mut connected = true;
+        let mut gen = 0;
+        while gen < gens {
+            let next = if self.interval > 0 { (gen + self.interval).min(gens) } else { gens };
+            self.gp.evolve(next - gen);
+            gen = next;
+            if gen < gens {
+                let batch = emigrants(&self.gp, self.migrants, self.emigration, &mut self.rng);
+                let programs: Vec<String> = batch.iter()
+                    .filter_map(|i| i.reconstruct(self.reconstruction_limit))
+                    .map(|i| encode(i.core()))
+                    .collect();
+                let message = migrants_message(programs.len(), &programs.join("\n"));
+                connected = connected && self.stream.write_all(message.as_bytes()).is_ok();
+                for immigrants in mailbox.try_iter() {
+                    // decoded immigrants have a tree, so their origin does not matter.
+                    let origin = self.gp.data().clone();
+                    immigrate(&mut self.gp,
+                              immigrants,
+                              &origin,
+                              self.replacement,
+                              self.reconstruction_limit,
+                              &mut self.rng);
+                }
+            }
+            connected = connected && self.report(gen).is_ok();
+        }
+        if connected {
+            writeln!(self.stream, "DONE")?;
+        }
+        // also ends the receiving thread.
+        let _ = self.stream.shutdown(Shutdown::Both);
+        let _ = receiver.join();
+        Ok(())
+    }
+
+    fn report(&mut self, generation: usize) -> io::Result<()> {
+        let best = self.gp.pop().get_first();
+        let error = |e: Option<Float>| e.map_or("-".to_string(), |e| format!("{:?}", e));
+        writeln!(self.stream,
+                 "STATS {} {} {} {}",
+                 generation,
+                 error(best.train()),
+                 error(best.test()),
+                 best.size())
+    }
+}
+
+/// Puts the migrants received from the coordinator into the mailbox, dropping those
+/// that do not fit it, until the connection closes.
+fn receive<B: BufRead>(mut lines: io::Lines<B>,
+                       dims: usize,
+                       mailbox: SyncSender<Vec<Individual>>) {
+    while let Some(Ok(line)) = lines.next() {
+        let count = match line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            ["MIGRANTS", count] => count.parse().unwrap_or(0),
+            _ => continue,
+        };
+        let immigrants: Vec<Individual> = lines.by_ref()
+            .take(count)
+            .filter_map(Result::ok)
+            .filter_map(|program| decode(&program, dims))
+            .map(Individual::from_core)
+            .collect();
+        if !immigrants.is_empty() {
+            let _ = mailbox.try_send(immigrants);
+        }
+    }
+}
+
+/// Writes a program in prefix notation as whitespace separated tokens.
+fn encode(core: &[Node]) -> String {
+    let tokens: Vec<String> = core.iter()
+        .map(|node| match *node {
+            Node::Addition => "+".to_string(),
+            Node::Subtraction => "-".to_string(),
+            Node::Multiplication => "*".to_string(),
+            Node::Division => "/".to_string(),
+            Node::Cosine => "cos".to_string(),
+            Node::LogFunction => "logistic".to_string(),
+            Node::Input(i) => format!("x{}", i),
+            Node::Constant(c) => format!("{:?}", c),
+        })
+        .collect();
+    tokens.join(" ")
+}
+
+/// Reads a program written by `encode()`, if it is a well-formed one over `dims` inputs.
+fn decode(program: &str, dims: usize) -> Option<Vec<Node>> {
+    let mut core = vec![];
+    // number of subtrees still expected.
+    let mut open = 1;
+    for token in program.split_whitespace() {
+        if open == 0 {
+            return None;
+        }
+        let node = match token {
+            "+" => Node::Addition,
+            "-" => Node::Subtraction,
+            "*" => Node::Multiplication,
+            "/" => Node::Division,
+            "cos" => Node::Cosine,
+            "logistic" => Node::LogFunction,
+            _ if token.starts_with('x') => {
+                let i: usize = token[1..].parse().ok()?;
+                if i >= dims {
+                    return None;
+                }
+                Node::Input(i)
+            }
+            _ => Node::Constant(token.parse().ok()?),
+        };
+        open = open - 1 + node.arity();
+        core.push(node);
+    }
+    if open == 0 { Some(core) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::problems;
+
+    /// Connects to `addr` as a worker would, returning the stream and its lines after
+    /// checking the welcome.
+    fn connect(addr: SocketAddr, island: usize) -> (TcpStream, io::Lines<BufReader<TcpStream>>) {
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), format!("WELCOME {}", island));
+        (stream, lines)
+    }
+
+    #[test]
+    fn coordinator_routes_migrants_and_reports_dropped_workers() {
+        let mut coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+        let addr = coordinator.local_addr().unwrap();
+        let run = thread::spawn(move || coordinator.run(2));
+        let (mut first, mut first_lines) = connect(addr, 0);
+        let (second, mut second_lines) = connect(addr, 1);
+        assert_eq!(first_lines.next().unwrap().unwrap(), "START");
+        assert_eq!(second_lines.next().unwrap().unwrap(), "START");
+
+        write!(first, "MIGRANTS 1\nx0\n").unwrap();
+        assert_eq!(second_lines.next().unwrap().unwrap(), "MIGRANTS 1");
+        assert_eq!(second_lines.next().unwrap().unwrap(), "x0");
+        write!(first, "STATS 2 0.5 - 3\nDONE\n").unwrap();
+        drop((second, second_lines));
+
+        let reports = run.join().unwrap().unwrap();
+        assert!(reports[0].finished && !reports[1].finished);
+        let stats = Stats {
+            generation: 2,
+            train: Some(0.5),
+            test: None,
+            size: 3,
+        };
+        assert_eq!(reports[0].stats, vec![stats]);
+    }
+
+    #[test]
+    fn workers_carry_on_when_another_drops_out_before_the_start() {
+        let mut coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+        let addr = coordinator.local_addr().unwrap();
+        let run = thread::spawn(move || coordinator.run(2));
+        drop(connect(addr, 0));
+
+        let data = problems::koza(1).data(&mut rng_from_seed(1));
+        let gp = GP::new_gp(data).set_pop_size(20).set_seed(1);
+        let mut worker = Worker::connect(addr, gp).unwrap().set_migration_interval(2);
+        assert_eq!(worker.island(), 1);
+        worker.run(6).unwrap();
+
+        let reports = run.join().unwrap().unwrap();
+        assert!(!reports[0].finished && reports[0].stats.is_empty());
+        assert!(reports[1].finished);
+        let generations: Vec<usize> = reports[1].stats.iter().map(|s| s.generation).collect();
+        assert_eq!(generations, vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn coordinator_stops_waiting_for_workers_after_the_timeout() {
+        let mut coordinator = Coordinator::bind("127.0.0.1:0")
+            .unwrap()
+            .set_accept_timeout(Some(Duration::from_millis(100)));
+        let addr = coordinator.local_addr().unwrap();
+        let run = thread::spawn(move || coordinator.run(2));
+        let _worker = connect(addr, 0);
+        let error = run.join().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn programs_survive_encoding() {
+        use core::node::Node::*;
+        let core = vec![Addition, Cosine, Input(1), Division, Constant(-0.25), LogFunction,
+                        Input(0)];
+        assert_eq!(decode(&encode(&core), 2), Some(core.clone()));
+        assert_eq!(decode(&encode(&core), 1), None);
+        assert_eq!(decode("+ x0", 1), None);
+        assert_eq!(decode("x0 x0", 1), None);
+    }
+}
@@ -0,0 +1,398 @@
+//! Ensembles: models made of several evolved programs, whose outputs are aggregated.
+//!
+//! Members are taken from a final population (its top-k individuals, or its Pareto
+//! front of training error vs. size) or from the islands of an `Mgp`, or evolved one
+//! after the other by bagging or gradient boosting. They need a tree
+//! to predict on new data: individuals evolved with `Variation::GeometricSemantic` are
+//! reconstructed (see `Individual::reconstruct()`) up to a given size, beyond which they
+//! are left out.
+
+use core::Float;
+use core::data::Data;
+use core::fitness::Fitness;
+use core::gp::GP;
+use core::individual::Individual;
+use core::matrix::Matrix;
+use core::multi_gp::Mgp;
+use core::utils::{solve, widen};
+use rand::Rng;
+
+/// How the outputs of the members are combined.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Aggregation {
+    /// Mean of the outputs.
+    Mean,
+    /// Median of the outputs.
+    Median,
+    /// `intercept + sum of weight * output`, e.g. fitted by `Ensemble::stack()`.
+    Weighted(Float, Vec<Float>),
+}
+
+/// A model aggregating the outputs of several programs.
+#[derive(Debug, Clone)]
+pub struct Ensemble {
+    members: Vec<Individual>,
+    aggregation: Aggregation,
+}
+
+impl Ensemble {
+    /// Returns an ensemble of `members`, aggregated by their mean.
+    pub fn from_members(members: Vec<Individual>) -> Ensemble {
+        if members.is_empty() {
+            panic!("@Ensemble::from_members() An ensemble needs members.");
+        }
+        if members.iter().any(|m| m.core().is_empty()) {
+            panic!("@Ensemble::from_members() Members need a tree, see Individual::reconstruct().");
+        }
+        Ensemble {
+            members,
+            aggregation: Aggregation::Mean,
+        }
+    }
+
+    /// Returns an ensemble of the `k` fittest individuals of the population of `gp`,
+    /// which is sorted, among those with a tree of at most `max_size` nodes.
+    pub fn top_k(gp: &GP, k: usize, max_size: usize) -> Ensemble {
+        let members = gp.pop()
+            .core()
+            .iter()
+            .filter(|ind| ind.size() <= max_size)
+            .take(k)
+            .filter_map(|ind| with_tree(gp, ind, max_size))
+            .collect();
+        Ensemble::from_members(members)
+    }
+
+    /// Returns an ensemble of the individuals of the population of `gp` that no other
+    /// beats on both training error and size, one per pair of error and size, among
+    /// those with a tree of at most `max_size` nodes.
+    pub fn pareto_front(gp: &GP, max_size: usize) -> Ensemble {
+        let mut candidates: Vec<&Individual> =
+            gp.pop().core().iter().filter(|ind| ind.size() <= max_size).collect();
+        candidates.sort_by(|a, b| a.cmp_train(b).then(a.size().cmp(&b.size())));
+        let mut front = vec![];
+        let mut smallest = usize::MAX;
+        // by increasing error, a member must be smaller than all the fitter ones.
+        for ind in candidates {
+            if ind.size() < smallest {
+                smallest = ind.size();
+                front.extend(with_tree(gp, ind, max_size));
+            }
+        }
+        Ensemble::from_members(front)
+    }
+
+    /// Returns an ensemble of the fittest individual of each island of `mgp`, over the
+    /// inputs of the `Data` the islands were added with (see `Mgp::island_bests()`).
+    /// Those without a tree over `max_size` nodes are left out.
+    pub fn from_islands(mgp: &Mgp, max_size: usize) -> Ensemble {
+        Ensemble::from_members(mgp.island_bests(max_size).into_iter().flatten().collect())
+    }
+
+    /// Bagging: evolves `gp` for `gens` generations on each of `bags` bootstrap samples
+    /// of its training set (see `Data::bootstrap()`), drawn with `rng`, and returns an
+    /// ensemble of the fittest individual of each run with a tree of at most `max_size`
+    /// nodes, aggregated by their mean. Runs without one are left out.
+    ///
+    /// Runs share the configuration of `gp`, including its random number generator.
+    pub fn bagging<R: Rng>(mut gp: GP,
+                           bags: usize,
+                           gens: usize,
+                           max_size: usize,
+                           rng: &mut R)
+                           -> Ensemble {
+        let data = gp.data().clone();
+        let mut members = vec![];
+        for _ in 0..bags {
+            gp = gp.set_data(data.bootstrap(rng));
+            gp.init_new_pop();
+            gp.evolve(gens);
+            members.extend(fittest(&gp, max_size));
+        }
+        Ensemble::from_members(members)
+    }
+
+    /// Gradient boosting (for the squared error): starting from the (weighted) mean of
+    /// the training targets, each of `stages` evolves `gp` for `gens` generations on the
+    /// residuals of the previous stages, both on training and test sets. The fittest
+    /// individual of each stage with a tree of at most `max_size` nodes joins the
+    /// ensemble with weight `learning_rate`, the intercept being the initial mean.
+    /// Stages without one leave the residuals as they are.
+    ///
+    /// Stages share the configuration of `gp`, including its random number generator.
+    pub fn boosting(mut gp: GP,
+                    stages: usize,
+                    gens: usize,
+                    learning_rate: Float,
+                    max_size: usize)
+                    -> Ensemble {
+        let data = gp.data().clone();
+        let targets = data.train_targets();
+        let total: Float = match data.train_weights() {
+            Some(w) => w.iter().sum(),
+            None => targets.len() as Float,
+        };
+        let mean = match data.train_weights() {
+            Some(w) => targets.iter().zip(w).map(|(t, w)| t * w).sum::<Float>() / total,
+            None => targets.iter().sum::<Float>() / total,
+        };
+        let mut train_residuals: Vec<Float> = targets.iter().map(|t| t - mean).collect();
+        let mut test_residuals: Vec<Float> = data.test_targets().iter().map(|t| t - mean).collect();
+        let mut members = vec![];
+        for _ in 0..stages {
+            gp = gp.set_data(data.clone().with_targets(train_residuals.clone(),
+                                                       test_residuals.clone()));
+            gp.init_new_pop();
+            gp.evolve(gens);
+            let member = match fittest(&gp, max_size) {
+                Some(member) => member,
+                None => continue,
+            };
+            let step = |residuals: &mut Vec<Float>, outputs: Vec<Float>| {
+                for (r, o) in residuals.iter_mut().zip(outputs) {
+                    *r -= learning_rate * o;
+                }
+            };
+            step(&mut train_residuals, member.predict(data.train()));
+            step(&mut test_residuals, member.predict(data.test()));
+            members.push(member);
+        }
+        let weights = vec![learning_rate; members.len()];
+        Ensemble::from_members(members).set_aggregation(Aggregation::Weighted(mean, weights))
+    }
+
+    /// Sets how the outputs of the members are combined.
+    pub fn set_aggregation(mut self, aggregation: Aggregation) -> Ensemble {
+        if let Aggregation::Weighted(_, ref weights) = aggregation {
+            if weights.len() != self.members.len() {
+                panic!("@Ensemble::set_aggregation() Expected one weight per member.");
+            }
+        }
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Returns this ensemble aggregated by stacking: the intercept and weights of the
+    /// members are fitted to the training targets of `data` by (weighted, if `data` has
+    /// weights) least squares. Falls back to the mean if they cannot be fitted.
+    pub fn stack(self, data: &Data) -> Ensemble {
+        let outputs = self.outputs(data.train());
+        let k = outputs.len();
+        let weight = |i: usize| data.train_weights().map_or(1.0, |w| widen(w[i]));
+        let mut a = vec![vec![0f64; k + 1]; k + 1];
+        let mut b = vec![0f64; k + 1];
+        for (i, target) in data.train_targets().iter().enumerate() {
+            // regressors: a constant for the intercept, then the output of each member.
+            let x: Vec<f64> =
+                Some(1.0).into_iter().chain(outputs.iter().map(|o| widen(o[i]))).collect();
+            for (r, (row, b_r)) in a.iter_mut().zip(&mut b).enumerate() {
+                *b_r += weight(i) * x[r] * widen(*target);
+                for (a_rc, x_c) in row.iter_mut().zip(&x) {
+                    *a_rc += weight(i) * x[r] * x_c;
+                }
+            }
+        }
+        // a touch of ridge regularisation, as members are often nearly collinear.
+        let ridge = 1e-8 * (0..k + 1).map(|r| a[r][r]).sum::<f64>() / (k + 1) as f64;
+        for (r, row) in a.iter_mut().enumerate().skip(1) {
+            row[r] += ridge;
+        }
+        let aggregation = match solve(a, b) {
+            Some(beta) => {
+                Aggregation::Weighted(beta[0] as Float,
+                                      beta[1..].iter().map(|w| *w as Float).collect())
+            }
+            None => Aggregation::Mean,
+        };
+        self.set_aggregation(aggregation)
+    }
+
+    pub fn members(&self) -> &[Individual] {
+        &self.members
+    }
+
+    pub fn aggregation(&self) -> &Aggregation {
+        &self.aggregation
+    }
+
+    /// Returns the aggregated outputs of the members on `df` (the inputs of `Data`).
+    pub fn predict(&self, df: &Matrix) -> Vec<Float> {
+        let outputs = self.outputs(df);
+        let k = outputs.len() as Float;
+        (0..df.rows())
+            .map(|i| {
+                let mut row: Vec<Float> = outputs.iter().map(|o| o[i]).collect();
+                match self.aggregation {
+                    Aggregation::Mean => row.iter().sum::<Float>() / k,
+                    Aggregation::Median => {
+                        row.sort_by(|a, b| a.total_cmp(b));
+                        let mid = row.len() / 2;
+                        if row.len().is_multiple_of(2) {
+                            (row[mid - 1] + row[mid]) / 2.0
+                        } else {
+                            row[mid]
+                        }
+                    }
+                    Aggregation::Weighted(intercept, ref weights) => {
+                        intercept + row.iter().zip(weights).map(|(o, w)| o * w).sum::<Float>()
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the training and test errors of the ensemble on `data` according to
+    /// `fitness`, with the weights of `data` if any.
+    pub fn errors(&self, data: &Data, fitness: &dyn Fitness) -> (Float, Float) {
+        let train = fitness.error(&self.predict(data.train()),
+                                  data.train_targets(),
+                                  data.train_weights());
+        let test = fitness.error(&self.predict(data.test()),
+                                 data.test_targets(),
+                                 data.test_weights());
+        (train, test)
+    }
+
+    /// Exports the ensemble as an infix expression over the exported members
+    /// (see `Individual::export()`). The median is written as a function `median(...)`.
+    pub fn export(&self) -> String {
+        let members: Vec<String> =
+            self.members.iter().map(|m| format!("({})", m.export())).collect();
+        match self.aggregation {
+            Aggregation::Mean => format!("({}) / {}", members.join(" + "), members.len()),
+            Aggregation::Median => format!("median({})", members.join(", ")),
+            Aggregation::Weighted(intercept, ref weights) => {
+                let terms: Vec<String> = weights.iter()
+                    .zip(&members)
+                    .map(|(w, m)| format!("{:?} * {}", w, m))
+                    .collect();
+                format!("{:?} + {}", intercept, terms.join(" + "))
+            }
+        }
+    }
+
+    /// Returns the outputs of each member on `df`, indexed as `[member][instance]`.
+    fn outputs(&self, df: &Matrix) -> Vec<Vec<Float>> {
+        self.members.iter().map(|m| m.predict(df)).collect()
+    }
+}
+
+/// Returns `ind`, an individual of `gp`, with a tree of at most `max_size` nodes,
+/// evaluated as by `gp` if reconstructed.
+fn with_tree(gp: &GP, ind: &Individual, max_size: usize) -> Option<Individual> {
+    if !ind.core().is_empty() {
+        return if ind.size() <= max_size { Some(ind.clone()) } else { None };
+    }
+    let mut ind = ind.reconstruct(max_size)?;
+    ind.compute_semantics(gp.data());
+    gp.evaluate_on(&mut ind, gp.data());
+    Some(ind)
+}
+
+/// Returns the fittest individual of `gp` with a tree of at most `max_size` nodes.
+fn fittest(gp: &GP, max_size: usize) -> Option<Individual> {
+    gp.pop()
+        .core()
+        .iter()
+        .filter(|ind| ind.size() <= max_size)
+        .find_map(|ind| with_tree(gp, ind, max_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fitness::Metric;
+    use core::problems;
+    use core::utils::rng_from_seed;
+
+    fn gsgp() -> GP {
+        let data = problems::koza(1).data(&mut rng_from_seed(1));
+        let mut gp = GP::new_gsgp(data).set_pop_size(20).set_seed(1);
+        gp.init_new_pop();
+        gp.evolve(3);
+        gp
+    }
+
+    fn close(a: Option<Float>, b: Option<Float>) -> bool {
+        let (a, b) = (a.unwrap(), b.unwrap());
+        (a - b).abs() <= 1e-3 * (1.0 + b.abs())
+    }
+
+    #[test]
+    fn geometric_semantic_populations_are_reconstructed() {
+        let gp = gsgp();
+        assert!(gp.pop().get_first().core().is_empty());
+        let top = Ensemble::top_k(&gp, 3, 10_000);
+        assert_eq!(top.members().len(), 3);
+        for (member, ind) in top.members().iter().zip(gp.pop().core()) {
+            assert_eq!(member.size(), ind.size());
+            assert!(close(member.train(), ind.train()));
+        }
+        let front = Ensemble::pareto_front(&gp, 10_000);
+        assert!(front.members().iter().all(|m| !m.core().is_empty()));
+        assert!(front.members().windows(2).all(|w| w[0].size() > w[1].size()));
+    }
+
+    #[test]
+    fn members_over_the_size_limit_are_left_out() {
+        let gp = gsgp();
+        let sizes: Vec<usize> = gp.pop().core().iter().map(|i| i.size()).collect();
+        let limit = (sizes.iter().min().unwrap() + sizes.iter().max().unwrap()) / 2;
+        let top = Ensemble::top_k(&gp, 20, limit);
+        let expected = sizes.iter().filter(|&&s| s <= limit).count();
+        assert_eq!(top.members().len(), expected);
+        assert!(top.members().iter().all(|m| m.size() <= limit));
+        assert!(Ensemble::pareto_front(&gp, limit).members().iter().all(|m| m.size() <= limit));
+    }
+
+    #[test]
+    fn bagging_and_boosting_respect_the_size_limit() {
+        let data = problems::koza(1).data(&mut rng_from_seed(1));
+        let gp = || GP::new_gsgp(data.clone()).set_pop_size(20).set_seed(1);
+        let bagged = Ensemble::bagging(gp(), 3, 2, 10_000, &mut rng_from_seed(2));
+        assert_eq!(bagged.members().len(), 3);
+        let boosted = Ensemble::boosting(gp(), 3, 2, 0.5, 10_000);
+        assert_eq!(boosted.members().len(), 3);
+        assert_eq!(*boosted.aggregation(),
+                   Aggregation::Weighted(data.train_targets().iter().sum::<Float>() /
+                                         data.train_targets().len() as Float,
+                                         vec![0.5; 3]));
+        // some individuals grow beyond it within 4 generations.
+        let limit = 100;
+        for ensemble in &[Ensemble::bagging(gp(), 3, 4, limit, &mut rng_from_seed(2)),
+                          Ensemble::boosting(gp(), 3, 4, 0.5, limit)] {
+            assert!(ensemble.members().iter().all(|m| m.size() <= limit));
+        }
+    }
+
+    #[test]
+    fn aggregations_combine_member_outputs() {
+        use core::node::Node::*;
+        let members = vec![Individual::from_core(vec![Input(0)]),
+                           Individual::from_core(vec![Constant(1.0)]),
+                           Individual::from_core(vec![Constant(5.0)])];
+        let df = Matrix::from_rows(&[vec![0.0], vec![3.0]]);
+        let ensemble = Ensemble::from_members(members);
+        assert_eq!(ensemble.predict(&df), vec![2.0, 3.0]);
+        let median = ensemble.clone().set_aggregation(Aggregation::Median);
+        assert_eq!(median.predict(&df), vec![1.0, 3.0]);
+        let weighted = ensemble.set_aggregation(Aggregation::Weighted(1.0, vec![2.0, 0.0, 1.0]));
+        assert_eq!(weighted.predict(&df), vec![6.0, 12.0]);
+    }
+
+    #[test]
+    fn stacking_fits_member_weights() {
+        use core::node::Node::*;
+        // targets 1 + 2 * x0 - x1, of which the members output x0 and x1.
+        let rows: Vec<Vec<Float>> =
+            (0..10).map(|i| vec![i as Float, (i * i % 7) as Float]).collect();
+        let targets: Vec<Float> = rows.iter().map(|r| 1.0 + 2.0 * r[0] - r[1]).collect();
+        let inputs = Matrix::from_rows(&rows);
+        let data = Data::from_matrices(inputs.clone(), targets.clone(), inputs, targets);
+        let members = vec![Individual::from_core(vec![Input(0)]),
+                           Individual::from_core(vec![Input(1)])];
+        let stacked = Ensemble::from_members(members).stack(&data);
+        let (train, test) = stacked.errors(&data, &Metric::Rmse);
+        assert!(train < 1e-3 && test < 1e-3);
+    }
+}
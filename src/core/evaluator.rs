@@ -0,0 +1,293 @@
+//! A stack machine to evaluate programs over whole columns of data.
+//!
+//! A program is compiled once into postfix instructions, which are then run over the
+//! columns of a dataset: inputs are borrowed from the dataset rather than copied, and
+//! intermediate results live in scratch columns that are recycled as soon as they are
+//! consumed. Scratch columns are kept in a `Scratch` that can be reused across
+//! evaluations (`Individual::compute_semantics()` keeps one per thread), so that
+//! evaluating a program allocates little more than its output.
+//!
+//! Element-wise operations are the same as in `Node::op` and are carried out by the slice
+//! kernels of `core::utils`, so results are identical.
+
+use std::cell::RefCell;
+use core::node::Node;
+use core::Float;
+use core::matrix::Matrix;
+use core::utils::{logistic, protected_division, zip_assign_left, zip_assign_right, zip_into,
+                  map_assign, map_into};
+
+/// A program compiled into postfix notation.
+#[derive(Debug, Clone)]
+pub struct Program {
+    code: Vec<Node>,
+}
+
+/// Reusable memory for `Program::eval()`.
+#[derive(Debug, Default)]
+pub struct Scratch {
+    /// Scratch columns, all of the same length.
+    columns: Vec<Vec<Float>>,
+    /// Indices of the scratch columns that are not in use.
+    free: Vec<usize>,
+    /// The operand stack.
+    stack: Vec<Operand>,
+}
+
+/// An entry of the operand stack: either a borrowed input column or a scratch column.
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Input(usize),
+    Scratch(usize),
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::new(Scratch::new());
+}
+
+impl Scratch {
+    /// Returns an empty `Scratch`; columns are allocated on demand.
+    pub fn new() -> Scratch {
+        Scratch {
+            columns: vec![],
+            free: vec![],
+            stack: vec![],
+        }
+    }
+
+    /// Prepares the scratch for columns of length `n`, releasing every column.
+    fn reset(&mut self, n: usize) {
+        if self.columns.first().is_some_and(|c| c.len() != n) {
+            self.columns.clear();
+        }
+        self.free.clear();
+        self.free.extend(0..self.columns.len());
+        self.stack.clear();
+    }
+
+    /// Returns the index of a column that is not in use.
+    fn acquire(&mut self, n: usize) -> usize {
+        match self.free.pop() {
+            Some(k) => k,
+            None => {
+                self.columns.push(vec![0.0 as Float; n]);
+                self.columns.len() - 1
+            }
+        }
+    }
+}
+
+impl Program {
+    /// Compiles a program written in prefix notation (as the `core` of an `Individual`).
+    pub fn compile(prefix: &[Node]) -> Program {
+        let mut code = Vec::with_capacity(prefix.len());
+        Program::inner_compile(prefix, &mut 0, &mut code);
+        if code.len() != prefix.len() {
+            panic!("@Program::compile() Program has trailing nodes.");
+        }
+        Program { code }
+    }
+
+    /// Recursive component of `compile()`: arguments first, then the node.
+    fn inner_compile(prefix: &[Node], idx: &mut usize, code: &mut Vec<Node>) {
+        let node = &prefix[*idx];
+        for _child_node in 0..node.arity() {
+            *idx += 1;
+            Program::inner_compile(prefix, idx, code);
+        }
+        code.push(node.clone());
+    }
+
+    /// Returns the instructions in postfix notation.
+    pub fn code(&self) -> &Vec<Node> {
+        &self.code
+    }
+
+    /// Evaluates the program on `df` (the inputs of `Data`) using a per-thread `Scratch`.
+    pub fn eval(&self, df: &Matrix) -> Vec<Float> {
+        SCRATCH.with(|s| self.eval_with(df, &mut s.borrow_mut()))
+    }
+
+    /// Evaluates the program on `df` (the inputs of `Data`) using the given `Scratch`.
+    pub fn eval_with(&self, df: &Matrix, scratch: &mut Scratch) -> Vec<Float> {
+        let n = df.rows();
+        scratch.reset(n);
+        for node in &self.code {
+            let result = match *node {
+                Node::Input(j) => Operand::Input(j),
+                Node::Constant(val) => {
+                    let k = scratch.acquire(n);
+                    scratch.columns[k].iter_mut().for_each(|v| *v = val);
+                    Operand::Scratch(k)
+                }
+                Node::Cosine => unary(scratch, df, n, |a| a.cos()),
+                Node::LogFunction => unary(scratch, df, n, logistic),
+                Node::Addition => binary(scratch, df, n, |a, b| a + b),
+                Node::Subtraction => binary(scratch, df, n, |a, b| a - b),
+                Node::Multiplication => binary(scratch, df, n, |a, b| a * b),
+                Node::Division => binary(scratch, df, n, protected_division),
+            };
+            scratch.stack.push(result);
+        }
+        match scratch.stack.pop() {
+            Some(Operand::Input(j)) => df.column(j).to_vec(),
+            Some(Operand::Scratch(k)) => scratch.columns[k].clone(),
+            None => panic!("@Program::eval_with() Empty program."),
+        }
+    }
+}
+
+/// Pops one operand and applies `f` to it, in place when it is a scratch column.
+fn unary<F>(scratch: &mut Scratch, df: &Matrix, n: usize, f: F) -> Operand
+    where F: Fn(Float) -> Float
+{
+    match scratch.stack.pop().expect("@evaluator::unary() Stack underflow.") {
+        Operand::Scratch(k) => {
+            map_assign(&mut scratch.columns[k], f);
+            Operand::Scratch(k)
+        }
+        Operand::Input(j) => {
+            let k = scratch.acquire(n);
+            map_into(&mut scratch.columns[k], df.column(j), f);
+            Operand::Scratch(k)
+        }
+    }
+}
+
+/// Pops two operands and applies `f` to them, writing over one of them if it is
+/// a scratch column. The other scratch column, if any, is released.
+fn binary<F>(scratch: &mut Scratch, df: &Matrix, n: usize, f: F) -> Operand
+    where F: Fn(Float, Float) -> Float
+{
+    let second = scratch.stack.pop().expect("@evaluator::binary() Stack underflow.");
+    let first = scratch.stack.pop().expect("@evaluator::binary() Stack underflow.");
+    match (first, second) {
+        (Operand::Scratch(ka), Operand::Scratch(kb)) => {
+            let (a, b) = pair_mut(&mut scratch.columns, ka, kb);
+            zip_assign_left(a, b, f);
+            scratch.free.push(kb);
+            Operand::Scratch(ka)
+        }
+        (Operand::Scratch(ka), Operand::Input(jb)) => {
+            zip_assign_left(&mut scratch.columns[ka], df.column(jb), f);
+            Operand::Scratch(ka)
+        }
+        (Operand::Input(ja), Operand::Scratch(kb)) => {
+            zip_assign_right(df.column(ja), &mut scratch.columns[kb], f);
+            Operand::Scratch(kb)
+        }
+        (Operand::Input(ja), Operand::Input(jb)) => {
+            let k = scratch.acquire(n);
+            zip_into(&mut scratch.columns[k], df.column(ja), df.column(jb), f);
+            Operand::Scratch(k)
+        }
+    }
+}
+
+/// Returns a mutable reference to column `a` and a shared one to column `b`, with `a != b`.
+fn pair_mut(columns: &mut [Vec<Float>], a: usize, b: usize) -> (&mut Vec<Float>, &Vec<Float>) {
+    if a < b {
+        let (left, right) = columns.split_at_mut(b);
+        (&mut left[a], &right[0])
+    } else {
+        let (left, right) = columns.split_at_mut(a);
+        (&mut right[0], &left[b])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::data::Data;
+    use core::individual::Individual;
+    use core::utils::rng_from_seed;
+    use rand::Rng;
+
+    /// Evaluates a prefix program recursively with `Node::op`, as the reference.
+    fn reference(prefix: &[Node], idx: &mut usize, df: &Matrix) -> Vec<Float> {
+        let node = &prefix[*idx];
+        match *node {
+            Node::Constant(val) => vec![val; df.rows()],
+            Node::Input(j) => df.column(j).to_vec(),
+            _ => {
+                let mut args = vec![];
+                for _child_node in 0..node.arity() {
+                    *idx += 1;
+                    args.push(reference(prefix, idx, df));
+                }
+                node.op(args)
+            }
+        }
+    }
+
+    /// Asserts that both outputs are identical, NaNs included.
+    fn assert_identical(a: &[Float], b: &[Float]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!(x.to_bits() == y.to_bits() || (x.is_nan() && y.is_nan()),
+                    "{} != {}",
+                    x,
+                    y);
+        }
+    }
+
+    #[test]
+    fn stack_machine_matches_recursive_evaluation_of_random_trees() {
+        let data = Data::new("yacht");
+        let mut rng = rng_from_seed(2);
+        let mut scratch = Scratch::new();
+        for _ in 0..1000 {
+            let depth = rng.gen_range(1, 8);
+            let ind = if rng.gen() {
+                Individual::grow(depth, &data, &mut rng)
+            } else {
+                Individual::full(depth, &data, &mut rng)
+            };
+            let program = Program::compile(ind.core());
+            for df in &[data.train(), data.test()] {
+                let expected = reference(ind.core(), &mut 0, df);
+                assert_identical(&program.eval(df), &expected);
+                // a scratch reused across datasets of different lengths.
+                assert_identical(&program.eval_with(df, &mut scratch), &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn stack_machine_evaluates_logistic_nodes() {
+        use core::node::Node::*;
+        let data = Data::new("yacht");
+        // logistic(x0 * x1) - cos(logistic(x0) / 0)
+        let prefix = vec![Subtraction,
+                          LogFunction,
+                          Multiplication,
+                          Input(0),
+                          Input(1),
+                          Cosine,
+                          Division,
+                          LogFunction,
+                          Input(0),
+                          Constant(0.0)];
+        assert_identical(&Program::compile(&prefix).eval(data.train()),
+                         &reference(&prefix, &mut 0, data.train()));
+    }
+
+    #[test]
+    fn compiled_code_is_postfix() {
+        use core::node::Node::*;
+        let program = Program::compile(&[Addition, Input(0), Cosine, Constant(1.0)]);
+        assert_eq!(*program.code(), vec![Input(0), Constant(1.0), Cosine, Addition]);
+    }
+
+    #[cfg(feature = "f64")]
+    #[test]
+    fn semantics_keep_double_precision() {
+        use core::node::Node::*;
+        let data = Data::new("yacht");
+        // (1 + 1e-10) - 1 is 0 in single precision.
+        let prefix = [Subtraction, Addition, Constant(1.0), Constant(1e-10), Constant(1.0)];
+        for v in Program::compile(&prefix).eval(data.train()) {
+            assert!((v - 1e-10).abs() < 1e-15, "{}", v);
+        }
+    }
+}
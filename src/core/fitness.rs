@@ -0,0 +1,194 @@
+//! Error metrics and the `Fitness` trait, which tells how the outputs of a program
+//! are scored against the targets.
+//!
+//! Errors are always minimised: a lower error is a fitter individual. Metrics that are
+//! maximised (as R²) are turned into errors. All metrics take optional per-instance
+//! weights (see `Data::with_weights()`); without them every instance weighs `1.0`.
+
+use core::Float;
+
+/// A function scoring outputs against targets. `GP` is configured with one
+/// (see `GP::set_fitness()`), which is then used to evaluate every individual.
+pub trait Fitness: Send + Sync {
+    /// Returns the error of `outputs` w.r.t. `targets`, weighting each instance by
+    /// `weights` if given. Lower is fitter.
+    fn error(&self, outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> Float;
+
+    /// Name under which errors are reported.
+    fn name(&self) -> &str;
+}
+
+/// Built-in error metrics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    /// Root mean squared error.
+    Rmse,
+    /// Mean squared error.
+    Mse,
+    /// Mean absolute error.
+    Mae,
+    /// Root mean squared error divided by the standard deviation of the targets.
+    Nrmse,
+    /// `1 - R²`, the fraction of the variance of the targets left unexplained.
+    RSquared,
+    /// Largest absolute error (each scaled by its weight, if any).
+    MaxAbsError,
+}
+
+impl Fitness for Metric {
+    fn error(&self, outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> Float {
+        match *self {
+            Metric::Rmse => rmse(outputs, targets, weights),
+            Metric::Mse => mse(outputs, targets, weights),
+            Metric::Mae => mae(outputs, targets, weights),
+            Metric::Nrmse => nrmse(outputs, targets, weights),
+            Metric::RSquared => 1.0 - r_squared(outputs, targets, weights),
+            Metric::MaxAbsError => max_abs_error(outputs, targets, weights),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match *self {
+            Metric::Rmse => "rmse",
+            Metric::Mse => "mse",
+            Metric::Mae => "mae",
+            Metric::Nrmse => "nrmse",
+            Metric::RSquared => "1 - r2",
+            Metric::MaxAbsError => "max abs error",
+        }
+    }
+}
+
+/// Mean squared error.
+pub fn mse(outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> Float {
+    check_lengths(outputs, targets, weights);
+    mean(outputs.iter().zip(targets).map(|(o, t)| (o - t).powi(2)), weights)
+}
+
+/// Root mean squared error. Without weights, the same as `utils::rmse()`.
+pub fn rmse(outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> Float {
+    mse(outputs, targets, weights).sqrt()
+}
+
+/// Mean absolute error.
+pub fn mae(outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> Float {
+    check_lengths(outputs, targets, weights);
+    mean(outputs.iter().zip(targets).map(|(o, t)| (o - t).abs()), weights)
+}
+
+/// Root mean squared error divided by the standard deviation of the targets,
+/// i.e. `1.0` for a program that outputs the mean of the targets.
+pub fn nrmse(outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> Float {
+    rmse(outputs, targets, weights) / variance(targets, weights).sqrt()
+}
+
+/// Coefficient of determination: `1.0` for a perfect fit, `0.0` for a program that
+/// outputs the mean of the targets, and negative for worse ones.
+pub fn r_squared(outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> Float {
+    1.0 - mse(outputs, targets, weights) / variance(targets, weights)
+}
+
+/// Largest absolute error, each scaled by its weight if any.
+/// NaN if any error is NaN, as the other metrics are.
+pub fn max_abs_error(outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) -> Float {
+    check_lengths(outputs, targets, weights);
+    let errors = outputs.iter().zip(targets).map(|(o, t)| (o - t).abs());
+    match weights {
+        Some(w) => errors.zip(w).map(|(e, w)| e * w).fold(0.0, nan_max),
+        None => errors.fold(0.0, nan_max),
+    }
+}
+
+/// Maximum of `a` and `b`, unlike `Float::max()` NaN if either is.
+fn nan_max(a: Float, b: Float) -> Float {
+    if a.is_nan() || b.is_nan() {
+        Float::NAN
+    } else {
+        a.max(b)
+    }
+}
+
+/// Variance of `x` around its (weighted) mean.
+fn variance(x: &[Float], weights: Option<&[Float]>) -> Float {
+    let m = mean(x.iter().cloned(), weights);
+    mean(x.iter().map(|v| (v - m).powi(2)), weights)
+}
+
+/// Mean of `values`, weighted by `weights` if given.
+fn mean<I>(values: I, weights: Option<&[Float]>) -> Float
+    where I: ExactSizeIterator<Item = Float>
+{
+    match weights {
+        Some(w) => {
+            let total: Float = w.iter().sum();
+            values.zip(w).map(|(v, w)| v * w).sum::<Float>() / total
+        }
+        None => {
+            let n = values.len() as Float;
+            values.sum::<Float>() / n
+        }
+    }
+}
+
+fn check_lengths(outputs: &[Float], targets: &[Float], weights: Option<&[Float]>) {
+    if outputs.len() != targets.len() || weights.is_some_and(|w| w.len() != targets.len()) {
+        panic!("cannot compute errors of vectors of different length!");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUTS: [Float; 4] = [1.0, 2.0, 3.0, 6.0];
+    const TARGETS: [Float; 4] = [1.0, 3.0, 3.0, 4.0];
+
+    fn close(a: Float, b: Float) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn metrics_of_known_errors() {
+        // errors 0, -1, 0, 2
+        assert!(close(Metric::Mse.error(&OUTPUTS, &TARGETS, None), 1.25));
+        assert!(close(Metric::Rmse.error(&OUTPUTS, &TARGETS, None), (1.25 as Float).sqrt()));
+        assert!(close(Metric::Mae.error(&OUTPUTS, &TARGETS, None), 0.75));
+        assert!(close(Metric::MaxAbsError.error(&OUTPUTS, &TARGETS, None), 2.0));
+        // targets have mean 2.75 and variance 1.1875.
+        assert!(close(Metric::RSquared.error(&OUTPUTS, &TARGETS, None), 1.25 / 1.1875));
+        assert!(close(Metric::Nrmse.error(&OUTPUTS, &TARGETS, None),
+                      (1.25 as Float / 1.1875).sqrt()));
+    }
+
+    #[test]
+    fn mean_of_the_targets_scores_as_a_baseline() {
+        let mean = [2.75; 4];
+        assert!(close(Metric::Nrmse.error(&mean, &TARGETS, None), 1.0));
+        assert!(close(Metric::RSquared.error(&mean, &TARGETS, None), 1.0));
+        assert!(close(Metric::Rmse.error(&TARGETS, &TARGETS, None), 0.0));
+    }
+
+    #[test]
+    fn weights_scale_the_contribution_of_instances() {
+        let weights = [1.0, 0.0, 1.0, 2.0];
+        // squared errors 0, 1, 0, 4, weighted (0 + 0 + 0 + 8) / 4
+        assert!(close(Metric::Mse.error(&OUTPUTS, &TARGETS, Some(&weights)), 2.0));
+        assert!(close(Metric::MaxAbsError.error(&OUTPUTS, &TARGETS, Some(&weights)), 4.0));
+        // unit weights are the same as none.
+        assert!(close(Metric::Mae.error(&OUTPUTS, &TARGETS, Some(&[1.0; 4])),
+                      Metric::Mae.error(&OUTPUTS, &TARGETS, None)));
+    }
+
+    #[test]
+    fn non_finite_outputs_give_non_finite_errors() {
+        let nan = [1.0, Float::NAN, 3.0, 4.0];
+        let inf = [1.0, Float::INFINITY, 3.0, 4.0];
+        for metric in [Metric::Rmse, Metric::Mse, Metric::Mae, Metric::Nrmse,
+                       Metric::RSquared, Metric::MaxAbsError] {
+            assert!(metric.error(&nan, &TARGETS, None).is_nan(), "{:?}", metric);
+            assert!(!metric.error(&inf, &TARGETS, None).is_finite(), "{:?}", metric);
+        }
+        assert_eq!(max_abs_error(&inf, &TARGETS, None), Float::INFINITY);
+        assert!(max_abs_error(&nan, &TARGETS, Some(&[1.0; 4])).is_nan());
+    }
+}
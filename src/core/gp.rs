@@ -1,16 +1,25 @@
 extern crate rand;
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, Rng, XorShiftRng};
+use std::cmp::Ordering;
+use std::sync::Arc;
 
 use core::population::Population;
 use core::individual::Individual;
 use core::data::Data;
 use core::individual::variation::{standard, geometric_semantic};
+use core::constant_optimization::{ConstantOptimization, optimize_constants};
+use core::utils::{rng_from_seed, split_rng};
+use core::cache::{SemanticCache, CacheStats};
+use core::fitness::{Fitness, Metric};
+use core::Float;
+use rayon::prelude::*;
 
 /// Enum to select the phenotypic search component of the algorithm. It serves to redirect
 /// to `pub` functions in the `population` module, where the selection algorithms
 /// are defined and other data such as a map of ranks is mantained in order to be
 /// able to perform, for example, rank selection.
-enum Selection {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
     /// `Tournament` selection draws a random sample from the population and picks the fittest.
     Tournament,
     FitnessProportionate, // TODO
@@ -19,7 +28,8 @@ enum Selection {
 
 /// Enum to select the genotypic search component (variation) of the algorithm.
 /// It serves to redirect to the `pub` functions in the `individual` module.
-enum Variation {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variation {
     /// `Standard` variation methods operate at random points of parent trees.
     Standard,
     /// `GeometricSemantic` methods operate on the semantics
@@ -27,7 +37,18 @@ enum Variation {
     /// however they increase the size of offspring dramatically, especially when using crossover.
     /// First field denotes mutation step,
     /// and the second whether this mutation is bounded to the codomain [0,1].
-    GeometricSemantic(f32, bool),
+    GeometricSemantic(Float, bool),
+}
+
+/// Enum to select what happens to offspring whose semantics are not all finite
+/// (e.g. programs that overflow on some instances).
+#[derive(Debug, Clone, Copy)]
+pub enum NonFinite {
+    /// Offspring are kept, with an infinite (i.e. the worst) error.
+    WorstFitness,
+    /// Offspring are discarded and bred again, for at most the given number of attempts,
+    /// after which the last one is kept with the worst error.
+    Reject(usize),
 }
 
 /// A struct to hold the state of a Genetic Programming (GP) run.
@@ -42,14 +63,26 @@ pub struct GP {
     crossover_probability: f32,
     pop_size: usize,
     pool_size: usize,
+    /// Maximum depth of the initial trees (ramped half-and-half).
+    init_depth: usize,
     selection_method: Selection,
     variation_method: Variation,
+    constant_optimization: Option<ConstantOptimization>,
+    linear_scaling: bool,
+    cache: Option<SemanticCache>,
+    non_finite_policy: NonFinite,
+    /// How individuals are evaluated, hence selected, sorted and reported.
+    fitness: Arc<dyn Fitness>,
+    /// Source of randomness of the run. Each offspring gets its own generator,
+    /// split from this one, so that runs are reproducible when seeded
+    /// regardless of how offspring are scheduled across threads.
+    rng: XorShiftRng,
 }
 
 impl GP {
     /// Returns a new standard `GP`, i.e. `variation_method: Variation::Standard`.
     /// It defaults to crossover rate of 0.9, population size of 100, pool size
-    /// of 4, and tournament selection.
+    /// of 4, tournament selection, and RMSE as fitness.
     pub fn new_gp(dataset: Data) -> GP {
         GP {
             data: dataset,
@@ -57,8 +90,15 @@ impl GP {
             crossover_probability: 0.9,
             pop_size: 100,
             pool_size: 4,
+            init_depth: 6,
             selection_method: Selection::Tournament,
             variation_method: Variation::Standard,
+            constant_optimization: None,
+            linear_scaling: false,
+            cache: None,
+            non_finite_policy: NonFinite::WorstFitness,
+            fitness: Arc::new(Metric::Rmse),
+            rng: split_rng(&mut thread_rng()),
         }
     }
 
@@ -74,8 +114,15 @@ impl GP {
             crossover_probability: 0.0,
             pop_size: 100,
             pool_size: 4,
+            init_depth: 6,
             selection_method: Selection::Tournament,
             variation_method: Variation::GeometricSemantic(1.0, true),
+            constant_optimization: None,
+            linear_scaling: false,
+            cache: None,
+            non_finite_policy: NonFinite::WorstFitness,
+            fitness: Arc::new(Metric::Rmse),
+            rng: split_rng(&mut thread_rng()),
         }
     }
 
@@ -93,12 +140,28 @@ impl GP {
         }
     }
 
+    /// Sets the `Data` to evolve on, discarding the population, if any, and the contents
+    /// of the semantic cache, which relate to the previous data.
+    pub fn set_data(mut self, data: Data) -> GP {
+        self.data = data;
+        self.pop = Population::new();
+        if self.cache.is_some() {
+            self.cache = Some(SemanticCache::new());
+        }
+        self
+    }
+
     /// Sets population size.
     pub fn set_pop_size(mut self, size: usize) -> GP {
         self.pop_size = size;
         self
     }
 
+    /// Returns the number of individuals the population is kept at.
+    pub fn pop_size(&self) -> usize {
+        self.pop_size
+    }
+
     /// Sets crossover rate, i.e. the rate at which crossover is performed instead of mutation.
     pub fn set_xo_rate(mut self, xo_rate: f32) -> GP {
         self.crossover_probability = xo_rate;
@@ -112,12 +175,109 @@ impl GP {
         self
     }
 
+    /// Sets the maximum depth of the trees of the initial population, 6 by default.
+    pub fn set_init_depth(mut self, depth: usize) -> GP {
+        if depth == 0 {
+            panic!("@GP::set_init_depth() Initial trees must have a depth of at least 1.");
+        }
+        self.init_depth = depth;
+        self
+    }
+
+    /// Sets the variation operators, according to the options provided by `enum Variation`.
+    pub fn set_variation_method(mut self, vm: Variation) -> GP {
+        self.variation_method = vm;
+        self
+    }
+
+    /// Toggles caching of subtree semantics across generations (see `core::cache`),
+    /// so that only the modified parts of offspring are evaluated. Lookups have a cost
+    /// of their own, so this pays off on datasets with many instances.
+    /// Has no effect with `Variation::GeometricSemantic`, whose individuals have no tree.
+    pub fn set_semantic_cache(mut self, enabled: bool) -> GP {
+        self.cache = if enabled {
+            Some(SemanticCache::new())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Returns hit and miss counts of the semantic cache, if enabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|c| c.stats())
+    }
+
+    /// Picks what happens to offspring with non-finite semantics,
+    /// according to the options provided by `enum NonFinite`.
+    pub fn set_non_finite_policy(mut self, policy: NonFinite) -> GP {
+        self.non_finite_policy = policy;
+        self
+    }
+
+    /// Sets the fitness function individuals are evaluated by (see `core::fitness`),
+    /// e.g. `Metric::Mae`. Selection and sorting go by its errors.
+    pub fn set_fitness<F: Fitness + 'static>(mut self, fitness: F) -> GP {
+        self.fitness = Arc::new(fitness);
+        self
+    }
+
+    /// Returns the fitness function individuals are evaluated by.
+    pub fn fitness(&self) -> &dyn Fitness {
+        &*self.fitness
+    }
+
+    /// Seeds the random number generator, making the run reproducible.
+    pub fn set_seed(mut self, seed: u64) -> GP {
+        self.rng = rng_from_seed(seed);
+        self
+    }
+
     /// Picks the selection method according to the options provided by `ènum Selection`
     pub fn set_selection_method(mut self, sm: Selection) -> GP {
         self.selection_method = sm;
         self
     }
 
+    /// Enables constant optimisation (see `core::constant_optimization`) along evolution:
+    /// every `every` generations, the constants of the fittest `fraction` of the population
+    /// are tuned by at most `iterations` Levenberg-Marquardt iterations.
+    /// Has no effect with `Variation::GeometricSemantic`, whose individuals have no tree.
+    /// Panics unless `every >= 1`, `0 < fraction <= 1` and `iterations >= 1`.
+    pub fn set_constant_optimization(mut self,
+                                     every: usize,
+                                     fraction: f32,
+                                     iterations: usize)
+                                     -> GP {
+        if every == 0 {
+            panic!("@GP::set_constant_optimization() `every` must be at least 1.");
+        }
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            panic!("@GP::set_constant_optimization() `fraction` must be in (0, 1].");
+        }
+        if iterations == 0 {
+            panic!("@GP::set_constant_optimization() `iterations` must be at least 1.");
+        }
+        self.constant_optimization = Some(ConstantOptimization {
+                                              every,
+                                              fraction,
+                                              iterations,
+                                          });
+        self
+    }
+
+    /// Toggles linear scaling (see `Individual::evaluate_scaled()`): individuals are
+    /// selected by the error of their outputs fitted with the optimal slope and intercept.
+    pub fn set_linear_scaling(mut self, linear_scaling: bool) -> GP {
+        self.linear_scaling = linear_scaling;
+        self
+    }
+
+    /// Returns the `Data` this `GP` evolves on.
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+
     /// Utility to return an immutable reference to the `pop`ulation of `GP`.
     pub fn pop(&self) -> &Population {
         &self.pop
@@ -128,10 +288,23 @@ impl GP {
         &mut self.pop
     }
 
-    /// Initializes new population using ramped-half-half initialization with maximum depth 6
-    /// and to a number of individuals specified by `pop_size`. Will replace existing `pop`ulation!
-    pub fn init_new_pop(&mut self) -> () {
-        self.pop = Population::new_rhh(self.pop_size, 6, &self.data);
+    /// Initializes new population using ramped-half-half initialization with maximum depth
+    /// `init_depth` and to a number of individuals specified by `pop_size`.
+    /// Will replace existing `pop`ulation!
+    /// Individuals are evaluated and sorted, so that selection works from the first generation.
+    pub fn init_new_pop(&mut self) {
+        let mut pop =
+            Population::new_rhh(self.pop_size, self.init_depth, &self.data, &mut self.rng);
+        let (data, linear_scaling, fitness) = (&self.data, self.linear_scaling, self.fitness());
+        pop.core_mut()
+            .par_iter_mut()
+            .for_each(|i| if linear_scaling {
+                          i.evaluate_scaled(data, fitness)
+                      } else {
+                          i.evaluate(data, fitness)
+                      });
+        pop.sort_by_te();
+        self.pop = pop;
     }
 
     /// Utility to print to the console the state of evolution of `GP`.
@@ -145,17 +318,17 @@ impl GP {
     fn print_state(&self) {
         let f = self.pop.get_first();
         println!("----------------------");
-        println!("train:\t{:?}", f.train().unwrap());
-        println!("test:\t{:?}", f.test().unwrap());
+        println!("train:\t{:?}\t({})", f.train().unwrap(), self.fitness.name());
+        println!("test:\t{:?}\t({})", f.test().unwrap(), self.fitness.name());
         println!("size:\t{:?}", f.size());
         println!("depth:\t{:?}\n\n", f.depth());
     }
 
     /// Matches `selection_method` to call the corresponding selection function
     /// in `core::population`.
-    fn select(&self) -> &Individual {
+    fn select<R: Rng>(&self, rng: &mut R) -> &Individual {
         match self.selection_method {
-            Selection::Tournament => self.pop.tournament_select(self.pool_size),
+            Selection::Tournament => self.pop.tournament_select(self.pool_size, rng),
             Selection::FitnessProportionate => {
                 self.pop.fitness_proportionate_select(self.pool_size)
             }
@@ -165,51 +338,173 @@ impl GP {
 
     /// Matches `variation_method` to call the corresponding crossover
     /// function in `core::individual::variation`.
-    fn crossover(&self, p1: &Individual, p2: &Individual, data: &Data) -> Individual {
+    fn crossover<R: Rng>(&self,
+                         p1: &Individual,
+                         p2: &Individual,
+                         data: &Data,
+                         rng: &mut R)
+                         -> Individual {
         match self.variation_method {
-            Variation::Standard => standard::crossover(p1, p2, data),
-            Variation::GeometricSemantic(_, _) => geometric_semantic::crossover(p1, p2, data),
+            Variation::Standard => {
+                standard::crossover(p1, p2, data, self.fitness(), self.cache.as_ref(), rng)
+            }
+            Variation::GeometricSemantic(_, _) => {
+                geometric_semantic::crossover(p1, p2, data, self.fitness(), rng)
+            }
         }
     }
 
     /// Matches `variation_method` to call the corresponding mutation
     /// function in `core::individual::variation`.
-    fn mutation(&self, p1: &Individual, data: &Data) -> Individual {
+    fn mutation<R: Rng>(&self, p1: &Individual, data: &Data, rng: &mut R) -> Individual {
         match self.variation_method {
-            Variation::Standard => standard::mutation(p1, data),
+            Variation::Standard => {
+                standard::mutation(p1, data, self.fitness(), self.cache.as_ref(), rng)
+            }
             Variation::GeometricSemantic(step, bounded) => {
-                geometric_semantic::mutation(p1, data, step, bounded)
+                geometric_semantic::mutation(p1, data, self.fitness(), step, bounded, rng)
             }
         }
     }
 
+    /// Breeds one offspring, abiding by `non_finite_policy`.
+    fn breed<R: Rng>(&self, rng: &mut R) -> Individual {
+        let attempts = match self.non_finite_policy {
+            NonFinite::WorstFitness => 1,
+            NonFinite::Reject(attempts) => attempts.max(1),
+        };
+        let mut offspring = self.breed_once(rng);
+        for _ in 1..attempts {
+            if offspring.has_finite_semantics() {
+                break;
+            }
+            offspring = self.breed_once(rng);
+        }
+        offspring
+    }
+
+    /// Selects parents and breeds one offspring out of them.
+    fn breed_once<R: Rng>(&self, rng: &mut R) -> Individual {
+        let p1 = self.select(rng);
+        let offspring = if rng.next_f32() < self.crossover_probability {
+            let p2 = self.select(rng); // &Individual
+            self.crossover(p1, p2, &self.data, rng) // self.crossover!!
+        } else {
+            self.mutation(p1, &self.data, rng)
+        };
+        self.rescaled(offspring)
+    }
+
     /// Evolves the `Population` contained in this GP for a `gens` generations.
+    ///
+    /// Offspring are bred and evaluated in parallel, each with its own random
+    /// number generator split from the one of this `GP`.
     pub fn evolve(&mut self, gens: usize) {
         if self.pop.size() == 0 {
             self.init_new_pop();
             println!("self.pop initialized.");
         }
-        let mut rng = thread_rng();
+        if let Some(ref mut cache) = self.cache {
+            // warm up the cache with the subtrees of the current population.
+            if cache.stats().entries == 0 {
+                let programs = self.pop.core().iter().map(|i| i.core().as_slice());
+                cache.warm_up(programs.filter(|core| !core.is_empty()), &self.data);
+            }
+        }
         for gen in 0..gens {
             println!("Gen {:?}", gen + 1);
+            let mut rngs: Vec<XorShiftRng> =
+                (0..self.pop.size()).map(|_| split_rng(&mut self.rng)).collect();
+            let gp = &*self;
+            let offspring: Vec<Individual> =
+                rngs.par_iter_mut().map(|rng| gp.breed(rng)).collect();
             let mut offspring_pop = Population::new();
-            while offspring_pop.size() < self.pop.size() {
-                let offspring: Individual;
-                let p1 = self.select();
-                if rng.next_f32() < self.crossover_probability {
-                    let p2 = self.select(); // &Individual
-                    offspring = self.crossover(p1, p2, &self.data); // self.crossover!!
-                } else {
-                    offspring = self.mutation(p1, &self.data);
-                }
-                offspring_pop.add_individual(offspring);
-            }
+            offspring_pop.add_individuals(offspring);
             self.pop = offspring_pop;
+            if let Some(ref mut cache) = self.cache {
+                cache.new_generation();
+            }
             self.pop.sort_by_te();
+            if let Some(co) = self.constant_optimization {
+                if (gen + 1) % co.every == 0 {
+                    self.optimize_constants(co.fraction, co.iterations);
+                }
+            }
             self.print_state();
         } // perhaps print final solution
     }
 
+    /// Tunes the constants of the fittest `fraction` of the population (at least one
+    /// individual) by at most `iterations` Levenberg-Marquardt iterations each,
+    /// in parallel, and sorts the population again. Assumes `pop` is sorted.
+    pub fn optimize_constants(&mut self, fraction: f32, iterations: usize) {
+        if let Variation::GeometricSemantic(_, _) = self.variation_method {
+            return;
+        }
+        let k = ((self.pop.size() as f32 * fraction).ceil() as usize).max(1);
+        let gp = &*self;
+        let optimized: Vec<Individual> = self.pop.core()
+            .par_iter()
+            .take(k)
+            .map(|i| optimize_constants(i, &gp.data, gp.fitness(), iterations))
+            .collect();
+        for (i, o) in self.pop.core_mut().iter_mut().zip(optimized) {
+            if o.cmp_train(i) != Ordering::Greater {
+                *i = o;
+            }
+        }
+        self.pop.sort_by_te();
+    }
+
+    /// Returns `migrant`, an `Individual` of another `GP` evolving on `origin`, fit to join
+    /// this one: with its semantics on this `GP`'s data and its errors computed by this `GP`.
+    ///
+    /// Migrants without a tree (evolved with `Variation::GeometricSemantic`) only have
+    /// their semantics. Under `Variation::GeometricSemantic`, they keep them if `origin`
+    /// is this `GP`'s data (see `Data::is_same()`). Otherwise they are reconstructed (see
+    /// `Individual::reconstruct()`), or refused (`None`) if over `max_size` nodes.
+    pub fn adopt(&self,
+                 migrant: Individual,
+                 origin: &Data,
+                 max_size: usize)
+                 -> Option<Individual> {
+        let keeps_semantics = match self.variation_method {
+            Variation::GeometricSemantic(..) => {
+                origin.is_same(&self.data) && migrant.has_semantics_for(&self.data)
+            }
+            Variation::Standard => false,
+        };
+        let mut ind = if migrant.core().is_empty() && !keeps_semantics {
+            migrant.reconstruct(max_size)?
+        } else {
+            migrant
+        };
+        if !ind.core().is_empty() {
+            ind.compute_semantics(&self.data);
+        }
+        self.evaluate_on(&mut ind, &self.data);
+        Some(ind)
+    }
+
+    /// Computes the errors of `ind`, whose semantics on `data` are computed, as this `GP`
+    /// does for its own individuals: by its fitness, with linear scaling if it uses it.
+    pub fn evaluate_on(&self, ind: &mut Individual, data: &Data) {
+        if self.linear_scaling {
+            ind.evaluate_scaled(data, self.fitness());
+        } else {
+            ind.evaluate(data, self.fitness());
+        }
+    }
+
+    /// Re-evaluates `ind` with linear scaling if this `GP` uses it.
+    /// Variation operators always return individuals evaluated without it.
+    fn rescaled(&self, mut ind: Individual) -> Individual {
+        if self.linear_scaling {
+            ind.evaluate_scaled(&self.data, self.fitness());
+        }
+        ind
+    }
+
     /// Removes excess individuals, the less fit.
     ///
     /// Under the hood, calls a function that sorts by te and then truncates a vector.
@@ -217,3 +512,28 @@ impl GP {
         self.pop.keep_k_best(self.pop_size);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Describes the program, size and errors of every individual after a seeded run.
+    fn run(gp: GP, seed: u64) -> Vec<String> {
+        let mut gp = gp.set_pop_size(20).set_seed(seed);
+        gp.init_new_pop();
+        gp.evolve(3);
+        gp.pop()
+            .core()
+            .iter()
+            .map(|i| format!("{:?} {} {:?} {:?}", i.core(), i.size(), i.train(), i.test()))
+            .collect()
+    }
+
+    #[test]
+    fn runs_with_the_same_seed_are_identical() {
+        let data = Data::new("yacht");
+        assert_eq!(run(GP::new_gp(data.clone()), 7), run(GP::new_gp(data.clone()), 7));
+        assert_ne!(run(GP::new_gp(data.clone()), 7), run(GP::new_gp(data.clone()), 8));
+        assert_eq!(run(GP::new_gsgp(data.clone()), 7), run(GP::new_gsgp(data), 7));
+    }
+}
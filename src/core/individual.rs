@@ -1,9 +1,18 @@
 use core::data::Data;
 use core::node::Node;
-use core::utils::rmse;
-use rand::{thread_rng, Rng};
-
-#[derive(Debug, Clone)]
+use core::utils::{linear_scaling_coefficients, scale};
+use core::tree::Tree;
+use core::simplification::simplify_tree;
+use core::evaluator::Program;
+use core::cache::SemanticCache;
+use core::fitness::Fitness;
+use core::Float;
+use core::matrix::Matrix;
+use rand::Rng;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default)]
 /// The struct to represent an individual
 /// whose core is a tree of nodes hereby capture by `Vec<Node>`.
 ///
@@ -18,19 +27,66 @@ pub struct Individual {
     /// Vector containing the node elements of the program this `Individual` represents.
     core: Vec<Node>,
     /// Outputs on training data
-    train_semantics: Option<Vec<f32>>,
+    train_semantics: Option<Vec<Float>>,
     /// Outputs on test data
-    test_semantics: Option<Vec<f32>>,
+    test_semantics: Option<Vec<Float>>,
     /// Training error
-    train: Option<f32>,
+    train: Option<Float>,
     /// Test error
-    test: Option<f32>,
+    test: Option<Float>,
     /// Number of nodes of Individual's core.
     /// It is an `Option` because this is deduced when using
     /// `core::gp::Variation::GeometricSemantic`.
     size: Option<usize>,
     /// Depth of the Individual.
     depth: Option<usize>,
+    /// Linear scaling coefficients `(intercept, slope)` applied to the outputs
+    /// when evaluated with `evaluate_scaled()`.
+    scaling: Option<(Float, Float)>,
+    /// How the program was built by geometric semantic operators, as it has no `core`.
+    lineage: Option<Arc<Lineage>>,
+}
+
+/// The program of an offspring of geometric semantic operators, stored by reference
+/// to its parents (shared with their other offspring) and the random trees involved,
+/// so that it can be reconstructed (see `Individual::reconstruct()`).
+#[derive(Debug)]
+enum Lineage {
+    /// A program with a `core`, e.g. of the initial population.
+    Tree(Vec<Node>),
+    /// `p1 * r + (1 - r) * p2`.
+    Crossover(Arc<Lineage>, Arc<Lineage>, Vec<Node>),
+    /// `p1 + step * (r1 - r2)`.
+    Mutation(Arc<Lineage>, Float, Vec<Node>, Vec<Node>),
+}
+
+impl Lineage {
+    /// Appends the program, in prefix notation, to `nodes`.
+    fn expand(&self, nodes: &mut Vec<Node>) {
+        match *self {
+            Lineage::Tree(ref core) => nodes.extend_from_slice(core),
+            Lineage::Crossover(ref p1, ref p2, ref r) => {
+                nodes.push(Node::Addition);
+                nodes.push(Node::Multiplication);
+                p1.expand(nodes);
+                nodes.extend_from_slice(r);
+                nodes.push(Node::Multiplication);
+                nodes.push(Node::Subtraction);
+                nodes.push(Node::Constant(1.0));
+                nodes.extend_from_slice(r);
+                p2.expand(nodes);
+            }
+            Lineage::Mutation(ref p1, step, ref r1, ref r2) => {
+                nodes.push(Node::Addition);
+                p1.expand(nodes);
+                nodes.push(Node::Multiplication);
+                nodes.push(Node::Constant(step));
+                nodes.push(Node::Subtraction);
+                nodes.extend_from_slice(r1);
+                nodes.extend_from_slice(r2);
+            }
+        }
+    }
 }
 
 impl Individual {
@@ -42,44 +98,78 @@ impl Individual {
     // meaning that you're trying to pull it out "movingly".
     // this operation is used for grow() trees of max_depth 6, so cloning it should be cheap.
 
+    /// Returns a reference to the program (prefix notation) of this `Individual`.
+    pub fn core(&self) -> &Vec<Node> {
+        &self.core
+    }
+
     /// Returns the training error
-    pub fn train(&self) -> Option<f32> {
+    pub fn train(&self) -> Option<Float> {
         self.train
     }
-    pub fn test(&self) -> Option<f32> {
+    pub fn test(&self) -> Option<Float> {
         self.test
     }
 
+    /// Compares training errors under a total order, where a missing error
+    /// (not yet evaluated) is the worst possible, as is any non-finite one.
+    /// `Ordering::Less` means that `self` is fitter than `other`.
+    pub fn cmp_train(&self, other: &Individual) -> Ordering {
+        error_key(self.train).total_cmp(&error_key(other.train))
+    }
+
+    /// Returns whether training and test semantics are computed and all finite.
+    pub fn has_finite_semantics(&self) -> bool {
+        let finite = |s: &Option<Vec<Float>>| {
+            s.as_ref().is_some_and(|s| s.iter().all(|v| v.is_finite()))
+        };
+        finite(&self.train_semantics) && finite(&self.test_semantics)
+    }
+
     pub fn size(&self) -> usize {
         // I think this will be better if returning a Result..
-        if self.core.len() > 0 {
-            return self.core.len();
+        if !self.core.is_empty() {
+            self.core.len()
         } else {
             self.size
                 .expect("Size is not computed, while Vec<Node> is empty.")
         }
     }
 
+    /// Returns the linear scaling coefficients `(intercept, slope)`, if any.
+    pub fn scaling(&self) -> Option<(Float, Float)> {
+        self.scaling
+    }
+
     pub fn depth(&self) -> usize {
         self.depth.expect("Depth not computed.")
     }
 
-    pub fn train_semantics(&self) -> Vec<f32> {
+    pub fn train_semantics(&self) -> Vec<Float> {
         self.train_semantics
             .clone()
             .expect("Train data semantics not computed.")
     }
 
-    pub fn test_semantics(&self) -> Vec<f32> {
+    pub fn test_semantics(&self) -> Vec<Float> {
         self.test_semantics
             .clone()
             .expect("Test data semantics not computed.")
     }
 
-    pub fn semantics(&self) -> (Vec<f32>, Vec<f32>) {
+    pub fn semantics(&self) -> (Vec<Float>, Vec<Float>) {
         (self.train_semantics(), self.test_semantics())
     }
 
+    /// Returns whether training and test semantics are computed, with an output for
+    /// each instance of `data`.
+    pub fn has_semantics_for(&self, data: &Data) -> bool {
+        let fits =
+            |s: &Option<Vec<Float>>, rows: usize| s.as_ref().is_some_and(|s| s.len() == rows);
+        fits(&self.train_semantics, data.train().rows()) &&
+        fits(&self.test_semantics, data.test().rows())
+    }
+
     /*
     The process of creating a new individual:
     - Create empty individual using Individual::new()
@@ -109,76 +199,86 @@ impl Individual {
             test: None,
             size: None,
             depth: None,
+            scaling: None,
+            lineage: None,
         }
     }
 
+    /// Returns an `Individual` whose program is `core`, with size and depth computed.
+    /// Semantics and errors are left to be computed, as for `full()` and `grow()`.
+    pub fn from_core(core: Vec<Node>) -> Individual {
+        let mut i = Individual::new();
+        i.plug_in_core(core);
+        i.size = Some(i.core.len());
+        i.compute_depth();
+        i
+    }
+
     /// Generate a random individual using full method.
-    pub fn full(max_depth: usize, data_ref: &Data) -> Individual {
+    pub fn full<R: Rng>(max_depth: usize, data_ref: &Data, rng: &mut R) -> Individual {
         let mut i: Individual = Individual::new();
-        i.inner_full(0, max_depth, data_ref.dims());
+        i.inner_full(0, max_depth, data_ref.dims(), rng);
         i
     }
 
     /// Recursive component of `full()`.
-    fn inner_full(&mut self, current_depth: usize, max_depth: usize, data_dims: usize) {
-        let mut rng = thread_rng();
+    fn inner_full<R: Rng>(&mut self,
+                          current_depth: usize,
+                          max_depth: usize,
+                          data_dims: usize,
+                          rng: &mut R) {
         if current_depth == max_depth {
             if rng.gen() {
-                self.core.push(Node::get_random_const());
+                self.core.push(Node::get_random_const(rng));
             } else {
-                self.core.push(Node::get_random_input(data_dims));
+                self.core.push(Node::get_random_input(data_dims, rng));
             }
         } else {
-            let n = Node::get_random_functional();
+            let n = Node::get_random_functional(rng);
             let a = n.arity();
             self.core.push(n); // n moved!
             for _child_node in 0..a {
-                self.inner_full(current_depth + 1, max_depth, data_dims);
+                self.inner_full(current_depth + 1, max_depth, data_dims, rng);
             }
         }
     }
 
     /// Generate a random individual using grow method.
-    pub fn grow(max_depth: usize, data_ref: &Data) -> Individual {
+    pub fn grow<R: Rng>(max_depth: usize, data_ref: &Data, rng: &mut R) -> Individual {
         let mut i: Individual = Individual::new();
-        i.inner_grow(0, max_depth, data_ref.dims());
+        i.inner_grow(0, max_depth, data_ref.dims(), rng);
         i
     }
 
     /// Recursive component of `grow()`.
-    fn inner_grow(&mut self, current_depth: usize, max_depth: usize, data_dims: usize) {
-        let mut rng = thread_rng();
+    fn inner_grow<R: Rng>(&mut self,
+                          current_depth: usize,
+                          max_depth: usize,
+                          data_dims: usize,
+                          rng: &mut R) {
         if current_depth == max_depth {
             if rng.gen() {
-                self.core.push(Node::get_random_const());
+                self.core.push(Node::get_random_const(rng));
             } else {
-                self.core.push(Node::get_random_input(data_dims));
+                self.core.push(Node::get_random_input(data_dims, rng));
+            }
+        } else if rng.gen() {
+            let n = Node::get_random_functional(rng);
+            let a = n.arity();
+            self.core.push(n);
+            for _child_node in 0..a {
+                self.inner_grow(current_depth + 1, max_depth, data_dims, rng);
             }
         } else {
+            // 50/50 gets a constant or an input node
             if rng.gen() {
-                let n = Node::get_random_functional();
-                let a = n.arity();
-                self.core.push(n);
-                for _child_node in 0..a {
-                    self.inner_grow(current_depth + 1, max_depth, data_dims);
-                }
+                self.core.push(Node::get_random_const(rng));
             } else {
-                // 50/50 gets a constant or an input node
-                if rng.gen() {
-                    self.core.push(Node::get_random_const());
-                } else {
-                    self.core.push(Node::get_random_input(data_dims));
-                }
+                self.core.push(Node::get_random_input(data_dims, rng));
             }
         }
     }
 
-    /// Returns a clone of the node at `idx`.
-    /// Serving Standard crossover and mutation.
-    fn get(&mut self, idx: usize) -> Node {
-        self.core[idx].clone()
-    }
-
     /// Inserts a node at the beginning of the program.
     /// So far only used for prepending a log function node in GSGP:
     /// such bounds the outputs to the codomain [0,1].
@@ -188,8 +288,8 @@ impl Individual {
 
     /// Computes depth by setting up the variables.
     pub fn compute_depth(&mut self) {
-        let ref mut node_idx = 0; // a reference to a mutable usize
-        let ref initial_depth = 0; // a reference to a usize
+        let node_idx = &mut 0; // a reference to a mutable usize
+        let initial_depth = &0; // a reference to a usize
         self.depth = Some(0);
         self.inner_compute_depth(node_idx, initial_depth);
     }
@@ -207,34 +307,136 @@ impl Individual {
         }
     }
 
-    /// Returns of vector of the outputs for all data instance.
-    /// Used privately by `compute_semantics()`.
-    fn output(&mut self, mut idx: usize, df: &Vec<Vec<f32>>) -> Vec<f32> {
-        let ref node = self.get(idx);
-        match node {
-            &Node::Constant(val) => vec![val; df[0].len()],
-            &Node::Input(j) => df[j].to_vec(), // a copy that can totally be consumed
-            _ => {
-                let mut args = vec![] as Vec<Vec<f32>>;
-                for _child_node in 0..node.arity() {
-                    idx += 1;
-                    args.push(self.output(idx, df));
-                }
-                node.op(args)
-            }
-        }
-    }
-
     /// Computes the semantics - outputs on training and test data - and stores in
     /// the respective fields.
+    /// The program is compiled once and evaluated by `core::evaluator`.
     pub fn compute_semantics(&mut self, data: &Data) {
-        self.train_semantics = Some(self.output(0, data.train()));
-        self.test_semantics = Some(self.output(0, data.test()));
+        let program = Program::compile(&self.core);
+        self.train_semantics = Some(program.eval(data.train()));
+        self.test_semantics = Some(program.eval(data.test()));
+    }
+
+    /// Computes the semantics as `compute_semantics()` does, reusing the semantics of
+    /// subtrees found in `cache` (see `core::cache`).
+    pub fn compute_semantics_cached(&mut self, data: &Data, cache: &SemanticCache) {
+        let (train, test) = cache.semantics(&self.core, data);
+        self.train_semantics = Some(train);
+        self.test_semantics = Some(test);
+    }
+
+    /// Computes training and test errors according to `fitness`, with the weights of
+    /// `data` if any. Semantics not all finite (e.g. overflowing programs), on training
+    /// or test data, yield infinite errors, i.e. the worst fitness.
+    pub fn evaluate(&mut self, data: &Data, fitness: &dyn Fitness) {
+        self.scaling = None;
+        let train = fitness.error(&self.train_semantics(),
+                                  data.train_targets(),
+                                  data.train_weights());
+        let test = fitness.error(&self.test_semantics(), data.test_targets(), data.test_weights());
+        self.train = Some(worst_if_nan(train));
+        self.test = Some(worst_if_nan(test));
+        self.worst_if_non_finite();
+    }
+
+    /// Evaluates with linear scaling (Keijzer, 2003): the outputs are fitted to the
+    /// training targets with the optimal intercept and slope (least squares) before
+    /// computing errors. Coefficients are stored and reused on test data and by `predict()`.
+    ///
+    /// Semantics are stored unscaled, so that geometric semantic operators keep
+    /// working on the outputs of the programs themselves. Coefficients are those that
+    /// minimise the unweighted squared error, whatever the `fitness`.
+    pub fn evaluate_scaled(&mut self, data: &Data, fitness: &dyn Fitness) {
+        let train_semantics = self.train_semantics();
+        let (a, b) = linear_scaling_coefficients(&train_semantics, data.train_targets());
+        self.scaling = Some((a, b));
+        let train = fitness.error(&scale(&train_semantics, a, b),
+                                  data.train_targets(),
+                                  data.train_weights());
+        let test = fitness.error(&scale(&self.test_semantics(), a, b),
+                                 data.test_targets(),
+                                 data.test_weights());
+        self.train = Some(worst_if_nan(train));
+        self.test = Some(worst_if_nan(test));
+        self.worst_if_non_finite();
     }
 
-    pub fn evaluate(&mut self, data: &Data) {
-        self.train = Some(rmse(&self.train_semantics(), data.train_targets()));
-        self.test = Some(rmse(&self.test_semantics(), data.test_targets()));
+    /// Gives the worst errors, `Float::INFINITY`, if the semantics are not all finite.
+    fn worst_if_non_finite(&mut self) {
+        if !self.has_finite_semantics() {
+            self.train = Some(Float::INFINITY);
+            self.test = Some(Float::INFINITY);
+        }
+    }
+
+    /// Returns the outputs of the program on `df` (the inputs of `Data`),
+    /// with linear scaling applied if the `Individual` was evaluated with it.
+    /// Only available for `Individual`s with a `core`.
+    pub fn predict(&self, df: &Matrix) -> Vec<Float> {
+        if self.core.is_empty() {
+            panic!("@Individual::predict() Cannot predict without a core.");
+        }
+        let outputs = Program::compile(&self.core).eval(df);
+        match self.scaling {
+            Some((a, b)) => scale(&outputs, a, b),
+            None => outputs,
+        }
+    }
+
+    /// Exports the program as an infix expression (see `Tree::to_infix()`),
+    /// including linear scaling if the `Individual` was evaluated with it.
+    pub fn export(&self) -> String {
+        if self.core.is_empty() {
+            panic!("@Individual::export() Cannot export without a core.");
+        }
+        let expression = Tree::from_prefix(&self.core).to_infix();
+        match self.scaling {
+            Some((a, b)) => format!("{:?} + {:?} * {}", a, b, expression),
+            None => expression,
+        }
+    }
+
+    /// Returns an algebraically simplified copy of this `Individual`
+    /// (see `core::simplification`).
+    ///
+    /// The simplified program computes the same outputs for finite values, hence semantics
+    /// and errors are carried over. Individuals without a `core` (i.e. evolved with
+    /// `Variation::GeometricSemantic`) are simply cloned.
+    pub fn simplify(&self) -> Individual {
+        if self.core.is_empty() {
+            return self.clone();
+        }
+        let tree = simplify_tree(Tree::from_prefix(&self.core));
+        let mut simplified = Individual::from_core(tree.to_prefix());
+        simplified.train_semantics = self.train_semantics.clone();
+        simplified.test_semantics = self.test_semantics.clone();
+        simplified.train = self.train;
+        simplified.test = self.test;
+        simplified.scaling = self.scaling;
+        simplified
+    }
+
+    /// Returns an `Individual` whose `core` is the program of this one, provided it has
+    /// at most `max_size` nodes. Offspring of `Variation::GeometricSemantic`, which have
+    /// no `core`, are reconstructed from their ancestors; beware that their size
+    /// grows quickly with crossover. Semantics and errors are left to be computed, as
+    /// for `from_core()`.
+    pub fn reconstruct(&self, max_size: usize) -> Option<Individual> {
+        let lineage = self.lineage()?;
+        if self.size() > max_size {
+            return None;
+        }
+        let mut core = Vec::with_capacity(self.size());
+        lineage.expand(&mut core);
+        Some(Individual::from_core(core))
+    }
+
+    /// Returns how the program was built, if it is known.
+    fn lineage(&self) -> Option<Arc<Lineage>> {
+        match self.lineage {
+            Some(ref lineage) => Some(lineage.clone()),
+            None if !self.core.is_empty() => Some(Arc::new(Lineage::Tree(self.core.clone()))),
+            None => None,
+        }
     }
 
     // from this point onwards, individual is no longer mutable and is considered complete!
@@ -287,6 +489,23 @@ impl Individual {
     }
 }
 
+/// Maps a NaN error to the worst one, `Float::INFINITY`.
+fn worst_if_nan(error: Float) -> Float {
+    if error.is_nan() {
+        Float::INFINITY
+    } else {
+        error
+    }
+}
+
+/// Key under which errors are compared: missing or NaN errors are the worst.
+fn error_key(error: Option<Float>) -> Float {
+    match error {
+        Some(e) => worst_if_nan(e),
+        None => Float::INFINITY,
+    }
+}
+
 
 /// A module holding the functions to create new `Individual`s from
 /// exisiting ones. Everything regarding Genotypic search should be contained here.
@@ -297,14 +516,21 @@ pub mod variation {
 
         use core::individual::Individual;
         use core::data::Data;
-        extern crate rand;
-        use rand::{thread_rng, Rng};
+        use core::cache::SemanticCache;
+        use core::fitness::Fitness;
+        use rand::Rng;
 
         /// Standard crossover. This function picks a random crossover point for p1 and p2.
         /// and replaces the resulting subtree of p2 to the crossover point in p1.
-        pub fn crossover(p1: &Individual, p2: &Individual, data: &Data) -> Individual {
+        /// Semantics are computed through `cache`, if given, and errors by `fitness`.
+        pub fn crossover<R: Rng>(p1: &Individual,
+                                 p2: &Individual,
+                                 data: &Data,
+                                 fitness: &dyn Fitness,
+                                 cache: Option<&SemanticCache>,
+                                 rng: &mut R)
+                                 -> Individual {
             let mut offspring = Individual::new();
-            let mut rng = thread_rng();
 
             let xo_point_p1 = rng.gen_range(0, p1.size());
             let xo_point_p2 = rng.gen_range(0, p2.size());
@@ -320,39 +546,52 @@ pub mod variation {
             offspring.plug_in_core(p2_subtree_copy);
             offspring.plug_in_core(p1_right_copy);
 
-            offspring.compute_semantics(data);
-            offspring.evaluate(data);
-            offspring.size = Some(offspring.core.len());
-            offspring.compute_depth();
-            offspring
+            finish(offspring, data, fitness, cache)
         }
 
         /// Standard mutation. To a copy of `p1`, replaces a subtree by a randomly `grow`n one.
-        pub fn mutation(p1: &Individual, data: &Data) -> Individual {
+        /// Semantics are computed through `cache`, if given, and errors by `fitness`.
+        pub fn mutation<R: Rng>(p1: &Individual,
+                                data: &Data,
+                                fitness: &dyn Fitness,
+                                cache: Option<&SemanticCache>,
+                                rng: &mut R)
+                                -> Individual {
             // here note that the random tree does not need to be evaluated,
             // have its depth computed, or its semantics computed.
             // It only makes sense to compute such things on the offspring.
             // The only thing we need from the random tree is its core.
             // This is why all the fields are an option.
             let mut offspring = Individual::new();
-            let mut rng = thread_rng();
 
             let mutation_point = rng.gen_range(0, p1.size());
             let subnodes_p1 = p1.count_subtree_nodes(mutation_point);
 
             let p1_left_copy = p1.outer_left_copy(mutation_point);
-            let mutation = Individual::grow(6, data);
+            let mutation = Individual::grow(6, data, rng);
             let p1_right_copy = p1.outer_right_copy(mutation_point + subnodes_p1);
 
             offspring.plug_in_core(p1_left_copy);
             offspring.plug_in_core(mutation.clone_core());
             offspring.plug_in_core(p1_right_copy);
 
-            offspring.compute_semantics(data);
-            offspring.evaluate(data);
+            finish(offspring, data, fitness, cache) // becomes immutable when returning ;)
+        }
+
+        /// Computes semantics, errors, size and depth of a freshly assembled offspring.
+        fn finish(mut offspring: Individual,
+                  data: &Data,
+                  fitness: &dyn Fitness,
+                  cache: Option<&SemanticCache>)
+                  -> Individual {
+            match cache {
+                Some(cache) => offspring.compute_semantics_cached(data, cache),
+                None => offspring.compute_semantics(data),
+            }
+            offspring.evaluate(data, fitness);
             offspring.size = Some(offspring.core.len());
             offspring.compute_depth();
-            offspring // becomes immutable when returning ;)
+            offspring
         }
 
     }
@@ -364,14 +603,24 @@ pub mod variation {
         use core::node::Node;
         use core::utils::{add, subtract, multiply};
         use core::data::Data;
-
-        /// Geometric semantic crossover.
-        pub fn crossover(p1: &Individual, p2: &Individual, data: &Data) -> Individual {
+        use core::fitness::Fitness;
+        use core::Float;
+        use rand::Rng;
+        use std::sync::Arc;
+        use core::individual::Lineage;
+
+        /// Geometric semantic crossover. Errors are computed by `fitness`.
+        pub fn crossover<R: Rng>(p1: &Individual,
+                                 p2: &Individual,
+                                 data: &Data,
+                                 fitness: &dyn Fitness,
+                                 rng: &mut R)
+                                 -> Individual {
             let p1_semantics = p1.semantics();
             let p2_semantics = p2.semantics();
             // the random tree is bounded to [0, 1], hence the log function node prepend
             // i.e.always bounded for gs crossover
-            let mut r1 = Individual::grow(6, data);
+            let mut r1 = Individual::grow(6, data, rng);
             r1.prepend_node(Node::LogFunction);
             r1.compute_depth();
             r1.compute_semantics(data);
@@ -385,20 +634,23 @@ pub mod variation {
             let mut offspring = Individual::new(); // empty in all kinds of info you can think of
             offspring.train_semantics = Some(train_semantics);
             offspring.test_semantics = Some(test_semantics);
-            offspring.evaluate(data);
+            offspring.evaluate(data, fitness);
             offspring.size = Some(calc_xo_offspring_size(p1, p2, &r1));
             offspring.depth = Some(calc_xo_offspring_depth(p1, p2, &r1));
+            offspring.lineage = p1.lineage()
+                .zip(p2.lineage())
+                .map(|(l1, l2)| Arc::new(Lineage::Crossover(l1, l2, r1.clone_core())));
             offspring
         }
 
-        fn gs_crossover_semantics(p1_semantics: Vec<f32>,
-                                  p2_semantics: Vec<f32>,
-                                  r1_semantics: Vec<f32>)
-                                  -> Vec<f32> {
+        fn gs_crossover_semantics(p1_semantics: Vec<Float>,
+                                  p2_semantics: Vec<Float>,
+                                  r1_semantics: Vec<Float>)
+                                  -> Vec<Float> {
             // offspring semantics <- t1 * rb + (1 - rb) * t2
             let n = p1_semantics.len();
             add(multiply(p1_semantics.to_vec(), r1_semantics.to_vec()),
-                multiply(subtract(vec![1f32; n], r1_semantics.to_vec()),
+                multiply(subtract(vec![1.0 as Float; n], r1_semantics.to_vec()),
                          p2_semantics.to_vec()))
         }
 
@@ -411,15 +663,17 @@ pub mod variation {
             max(deepest + 2, r1.depth() + 3 + 1)
         }
 
-        /// Geometric Semantic Mutation
-        pub fn mutation(p1: &Individual,
-                        data: &Data,
-                        mut_step: f32,
-                        bounded_mutation: bool)
-                        -> Individual {
+        /// Geometric Semantic Mutation. Errors are computed by `fitness`.
+        pub fn mutation<R: Rng>(p1: &Individual,
+                                data: &Data,
+                                fitness: &dyn Fitness,
+                                mut_step: Float,
+                                bounded_mutation: bool,
+                                rng: &mut R)
+                                -> Individual {
             // Tm = T + ms (r1 - r2)
-            let mut r1 = Individual::grow(6, data);
-            let mut r2 = Individual::grow(6, data);
+            let mut r1 = Individual::grow(6, data, rng);
+            let mut r2 = Individual::grow(6, data, rng);
             if bounded_mutation {
                 r1.prepend_node(Node::LogFunction);
                 r2.prepend_node(Node::LogFunction);
@@ -441,17 +695,20 @@ pub mod variation {
             let mut offspring = Individual::new(); // empty in all kinds of info you can think of
             offspring.train_semantics = Some(train_semantics);
             offspring.test_semantics = Some(test_semantics);
-            offspring.evaluate(data);
+            offspring.evaluate(data, fitness);
             offspring.size = Some(calc_mut_offspring_size(p1, &r1, &r2));
             offspring.depth = Some(calc_mut_offspring_depth(p1, &r1, &r2));
+            offspring.lineage = p1.lineage().map(|l1| {
+                Arc::new(Lineage::Mutation(l1, mut_step, r1.clone_core(), r2.clone_core()))
+            });
             offspring
         }
 
-        fn gs_mutation_semantics(p1_sems: Vec<f32>,
-                                 r1_sems: Vec<f32>,
-                                 r2_sems: Vec<f32>,
-                                 mut_step: f32)
-                                 -> Vec<f32> {
+        fn gs_mutation_semantics(p1_sems: Vec<Float>,
+                                 r1_sems: Vec<Float>,
+                                 r2_sems: Vec<Float>,
+                                 mut_step: Float)
+                                 -> Vec<Float> {
             let len = r1_sems.len();
             add(p1_sems.to_vec(),
                 multiply(vec![mut_step; len],
@@ -470,3 +727,58 @@ pub mod variation {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fitness::{Metric, rmse};
+
+    #[test]
+    fn linear_scaling_fits_and_applies_its_coefficients() {
+        let data = Data::new("yacht");
+        let mut ind = Individual::from_core(vec![Node::Input(5)]);
+        ind.compute_semantics(&data);
+        ind.evaluate(&data, &Metric::Rmse);
+        let unscaled = ind.train().unwrap();
+        ind.evaluate_scaled(&data, &Metric::Rmse);
+        let (a, b) = ind.scaling().unwrap();
+        assert!(ind.train().unwrap() < unscaled);
+
+        let predictions = ind.predict(data.train());
+        for (p, x) in predictions.iter().zip(data.train().column(5)) {
+            assert!((p - (a + b * x)).abs() <= 1e-4 * p.abs().max(1.0));
+        }
+        let error = rmse(&predictions, data.train_targets(), None);
+        assert!((error - ind.train().unwrap()).abs() < 1e-4);
+        assert_eq!(ind.export(), format!("{:?} + {:?} * x5", a, b));
+        ind.evaluate(&data, &Metric::Rmse);
+        assert_eq!((ind.scaling(), ind.train()), (None, Some(unscaled)));
+    }
+
+    #[test]
+    fn non_finite_individuals_get_the_worst_errors_and_sort_last() {
+        use core::population::Population;
+        let data = Data::new("yacht");
+        // MAX * MAX * x5 overflows, as does its scaled version.
+        let max = Node::Constant(Float::MAX);
+        let overflowing = vec![Node::Multiplication, max.clone(), Node::Multiplication, max,
+                               Node::Input(5)];
+        let mut pop = Population::new();
+        for (core, scaled) in [(overflowing.clone(), false), (vec![Node::Input(0)], false),
+                               (overflowing, true), (vec![Node::Input(5)], true)] {
+            let mut ind = Individual::from_core(core);
+            ind.compute_semantics(&data);
+            if scaled {
+                ind.evaluate_scaled(&data, &Metric::Rmse);
+            } else {
+                ind.evaluate(&data, &Metric::Rmse);
+            }
+            assert_eq!(ind.has_finite_semantics(), ind.train().unwrap().is_finite());
+            assert_eq!(ind.has_finite_semantics(), ind.test().unwrap().is_finite());
+            pop.add_individual(ind);
+        }
+        pop.sort_by_te();
+        let finite: Vec<bool> = pop.core().iter().map(|i| i.has_finite_semantics()).collect();
+        assert_eq!(finite, vec![true, true, false, false]);
+    }
+}
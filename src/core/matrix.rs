@@ -0,0 +1,187 @@
+//! A column-major matrix over a single contiguous buffer.
+//!
+//! Each column (a variable, for all instances) is a contiguous slice of the buffer, so
+//! that programs are evaluated over borrowed columns. The buffer is reference counted:
+//! cloning a `Matrix` and taking a range of its rows or columns are views that share it,
+//! which makes splits of a dataset cheap to hand over to other populations or threads.
+
+use std::ops::Range;
+use std::sync::Arc;
+use core::Float;
+
+/// A column-major matrix, or a view of one. See the module documentation.
+#[derive(Debug, Clone)]
+pub struct Matrix {
+    /// Columns one after the other, each of length `stride`.
+    buffer: Arc<[Float]>,
+    /// Length of a column of the buffer.
+    stride: usize,
+    /// First row and first column of this view in the buffer.
+    row_offset: usize,
+    col_offset: usize,
+    rows: usize,
+    cols: usize,
+}
+
+impl Matrix {
+    /// Returns a matrix whose columns are those given, of equal length.
+    pub fn from_columns(columns: &[Vec<Float>]) -> Matrix {
+        let rows = columns.first().map_or(0, |c| c.len());
+        if columns.iter().any(|c| c.len() != rows) {
+            panic!("@Matrix::from_columns() Columns of different length.");
+        }
+        Matrix::from_buffer(columns.concat(), rows, columns.len())
+    }
+
+    /// Returns a matrix whose rows (instances) are those given, of equal length.
+    pub fn from_rows(rows: &[Vec<Float>]) -> Matrix {
+        let cols = rows.first().map_or(0, |r| r.len());
+        if rows.iter().any(|r| r.len() != cols) {
+            panic!("@Matrix::from_rows() Rows of different length.");
+        }
+        let mut buffer = Vec::with_capacity(rows.len() * cols);
+        for j in 0..cols {
+            buffer.extend(rows.iter().map(|r| r[j]));
+        }
+        Matrix::from_buffer(buffer, rows.len(), cols)
+    }
+
+    /// Returns a matrix of `rows` x `cols` over a buffer holding its columns one after
+    /// the other.
+    pub fn from_buffer(buffer: Vec<Float>, rows: usize, cols: usize) -> Matrix {
+        if buffer.len() != rows * cols {
+            panic!("@Matrix::from_buffer() Buffer length is not rows * cols.");
+        }
+        Matrix {
+            buffer: buffer.into(),
+            stride: rows,
+            row_offset: 0,
+            col_offset: 0,
+            rows,
+            cols,
+        }
+    }
+
+    /// Number of rows (instances).
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of columns (variables).
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Returns column `j`, borrowed from the buffer.
+    #[inline]
+    pub fn column(&self, j: usize) -> &[Float] {
+        if j >= self.cols {
+            panic!("@Matrix::column() Column {} out of {}.", j, self.cols);
+        }
+        let start = (self.col_offset + j) * self.stride + self.row_offset;
+        &self.buffer[start..start + self.rows]
+    }
+
+    /// Returns an iterator over the columns.
+    pub fn columns(&self) -> impl Iterator<Item = &[Float]> {
+        (0..self.cols).map(move |j| self.column(j))
+    }
+
+    /// Returns a view of the rows in `range`, sharing the buffer.
+    pub fn row_range(&self, range: Range<usize>) -> Matrix {
+        if range.start > range.end || range.end > self.rows {
+            panic!("@Matrix::row_range() Rows {:?} out of {}.", range, self.rows);
+        }
+        Matrix {
+            row_offset: self.row_offset + range.start,
+            rows: range.end - range.start,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a view of the columns in `range`, sharing the buffer.
+    pub fn column_range(&self, range: Range<usize>) -> Matrix {
+        if range.start > range.end || range.end > self.cols {
+            panic!("@Matrix::column_range() Columns {:?} out of {}.", range, self.cols);
+        }
+        Matrix {
+            col_offset: self.col_offset + range.start,
+            cols: range.end - range.start,
+            ..self.clone()
+        }
+    }
+
+    /// Returns whether both are views of the same rows and columns of the same buffer,
+    /// which is stricter than holding equal values.
+    pub fn is_same(&self, other: &Matrix) -> bool {
+        Arc::ptr_eq(&self.buffer, &other.buffer) &&
+        (self.row_offset, self.col_offset, self.rows, self.cols) ==
+        (other.row_offset, other.col_offset, other.rows, other.cols)
+    }
+
+    /// Returns a new matrix made of the given rows, in that order and possibly repeated
+    /// (e.g. a bootstrap sample). Unlike ranges, this copies.
+    pub fn select_rows(&self, rows: &[usize]) -> Matrix {
+        let mut buffer = Vec::with_capacity(rows.len() * self.cols);
+        for column in self.columns() {
+            buffer.extend(rows.iter().map(|&i| column[i]));
+        }
+        Matrix::from_buffer(buffer, rows.len(), self.cols)
+    }
+
+    /// Returns a new matrix made of the given columns, in that order. This copies.
+    pub fn select_columns(&self, cols: &[usize]) -> Matrix {
+        let mut buffer = Vec::with_capacity(self.rows * cols.len());
+        for &j in cols {
+            buffer.extend_from_slice(self.column(j));
+        }
+        Matrix::from_buffer(buffer, self.rows, cols.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4 x 3 matrix whose element at row `i` and column `j` is `10 * i + j`.
+    fn matrix() -> Matrix {
+        let rows: Vec<Vec<Float>> =
+            (0..4).map(|i| (0..3).map(|j| (10 * i + j) as Float).collect()).collect();
+        Matrix::from_rows(&rows)
+    }
+
+    #[test]
+    fn rows_are_stored_by_columns() {
+        let m = matrix();
+        assert_eq!((m.rows(), m.cols()), (4, 3));
+        assert_eq!(m.column(1), &[1.0, 11.0, 21.0, 31.0]);
+        let columns: Vec<Vec<Float>> = m.columns().map(|c| c.to_vec()).collect();
+        assert_eq!(Matrix::from_columns(&columns).column(2), m.column(2));
+    }
+
+    #[test]
+    fn views_share_the_buffer() {
+        let m = matrix();
+        let view = m.row_range(1..3).column_range(1..3);
+        assert_eq!((view.rows(), view.cols()), (2, 2));
+        assert_eq!(view.column(0), &[11.0, 21.0]);
+        assert_eq!(view.column(1), &[12.0, 22.0]);
+        assert!(Arc::ptr_eq(&view.buffer, &m.buffer));
+    }
+
+    #[test]
+    fn selections_copy_in_the_given_order() {
+        let m = matrix();
+        let rows = m.row_range(1..4).select_rows(&[2, 0, 0]);
+        assert_eq!(rows.column(0), &[30.0, 10.0, 10.0]);
+        let cols = m.select_columns(&[2, 0]);
+        assert_eq!(cols.column(0), m.column(2));
+        assert_eq!(cols.column(1), m.column(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Column 3 out of 3")]
+    fn columns_out_of_range_are_rejected() {
+        matrix().column(3);
+    }
+}
@@ -0,0 +1,438 @@
+//! Meta-GA: a genetic algorithm tuning the configuration of `GP` runs.
+//!
+//! Genomes are `Config`s, drawn from a `SearchSpace`. The fitness of a genome is its
+//! validation error: the mean test error, over the folds of the training set (see
+//! `Data::folds()`), of the fittest individual of short runs configured by it. The runs
+//! of a generation are evaluated in parallel; the fittest configuration is returned.
+
+use core::Float;
+use core::data::Data;
+use core::gp::{GP, Selection, Variation};
+use core::multi_gp::{Migrants, Mgp};
+use core::utils::{rng_from_seed, split_rng, widen};
+use rand::{thread_rng, Rng, XorShiftRng};
+use rayon::prelude::*;
+
+/// The parameters of a run tuned by `MetaGa`. Those not tuned are left to the `GP`
+/// the configuration is applied to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    /// Total number of individuals, split evenly among the islands.
+    pub pop_size: usize,
+    pub pool_size: usize,
+    pub xo_rate: f32,
+    pub selection: Selection,
+    /// Whether to use `Variation::GeometricSemantic`, with `mutation_step`, or
+    /// `Variation::Standard`.
+    pub geometric_semantic: bool,
+    pub mutation_step: Float,
+    /// Maximum depth of the initial trees, see `GP::set_init_depth()`.
+    pub init_depth: usize,
+    /// Number of islands of the `Mgp`, a single population if 1.
+    pub islands: usize,
+    pub migration_interval: usize,
+    /// Number of emigrants per island at each migration.
+    pub migrants: usize,
+}
+
+impl Config {
+    /// Returns `gp` configured as one of the `islands` of this configuration.
+    pub fn apply(&self, gp: GP) -> GP {
+        let variation = if self.geometric_semantic {
+            Variation::GeometricSemantic(self.mutation_step, true)
+        } else {
+            Variation::Standard
+        };
+        gp.set_pop_size((self.pop_size / self.islands).max(1))
+            .set_pool_size(self.pool_size)
+            .set_xo_rate(self.xo_rate)
+            .set_selection_method(self.selection)
+            .set_variation_method(variation)
+            .set_init_depth(self.init_depth)
+    }
+
+    /// Returns an `Mgp` of this configuration, whose islands are the `GP`s returned by
+    /// `gp` out of `data`, seeded from `seed`. It still has to be initialized.
+    pub fn mgp<F>(&self, data: &Data, seed: u64, gp: F) -> Mgp
+        where F: Fn(Data) -> GP
+    {
+        let mut rng = rng_from_seed(seed);
+        let mut mgp = Mgp::new()
+            .set_migrants(Migrants::Count(self.migrants))
+            .set_migration_interval(self.migration_interval)
+            .set_seed(rng.gen());
+        for _ in 0..self.islands {
+            mgp = mgp.add_subpop(self.apply(gp(data.clone())).set_seed(rng.gen()));
+        }
+        mgp
+    }
+}
+
+/// The values each parameter of a `Config` can take. Ranges are inclusive.
+#[derive(Debug, Clone)]
+pub struct SearchSpace {
+    pub pop_size: (usize, usize),
+    pub pool_size: (usize, usize),
+    pub xo_rate: (f32, f32),
+    pub selections: Vec<Selection>,
+    pub geometric_semantic: Vec<bool>,
+    pub mutation_step: (Float, Float),
+    pub init_depth: (usize, usize),
+    pub islands: (usize, usize),
+    pub migration_interval: (usize, usize),
+    pub migrants: (usize, usize),
+}
+
+impl Default for SearchSpace {
+    /// Only `Selection::Tournament` is searched, as the others are not implemented yet.
+    fn default() -> SearchSpace {
+        SearchSpace {
+            pop_size: (20, 200),
+            pool_size: (2, 8),
+            xo_rate: (0.0, 1.0),
+            selections: vec![Selection::Tournament],
+            geometric_semantic: vec![false, true],
+            mutation_step: (0.1, 2.0),
+            init_depth: (2, 8),
+            islands: (1, 4),
+            migration_interval: (5, 30),
+            migrants: (1, 10),
+        }
+    }
+}
+
+/// Number of genes of a `Config`.
+const GENES: usize = 10;
+
+impl SearchSpace {
+    /// Returns a configuration drawn uniformly at random.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Config {
+        let mut config = Config {
+            pop_size: 0,
+            pool_size: 0,
+            xo_rate: 0.0,
+            selection: Selection::Tournament,
+            geometric_semantic: false,
+            mutation_step: 0.0,
+            init_depth: 0,
+            islands: 0,
+            migration_interval: 0,
+            migrants: 0,
+        };
+        for gene in 0..GENES {
+            self.draw(&mut config, gene, rng);
+        }
+        config
+    }
+
+    /// Draws the `gene`-th parameter of `config` anew.
+    fn draw<R: Rng>(&self, config: &mut Config, gene: usize, rng: &mut R) {
+        fn int<R: Rng>(range: (usize, usize), rng: &mut R) -> usize {
+            rng.gen_range(range.0, range.1 + 1)
+        }
+        fn real<R: Rng>(range: (f64, f64), rng: &mut R) -> f64 {
+            if range.0 < range.1 { rng.gen_range(range.0, range.1) } else { range.0 }
+        }
+        fn choice<T: Copy, R: Rng>(choices: &[T], rng: &mut R) -> T {
+            *rng.choose(choices).expect("@SearchSpace::sample() Nothing to choose from.")
+        }
+        match gene {
+            0 => config.pop_size = int(self.pop_size, rng),
+            1 => config.pool_size = int(self.pool_size, rng),
+            2 => {
+                config.xo_rate = real((self.xo_rate.0 as f64, self.xo_rate.1 as f64), rng) as f32
+            }
+            3 => config.selection = choice(&self.selections, rng),
+            4 => config.geometric_semantic = choice(&self.geometric_semantic, rng),
+            5 => {
+                let range = (widen(self.mutation_step.0), widen(self.mutation_step.1));
+                config.mutation_step = real(range, rng) as Float
+            }
+            6 => config.init_depth = int(self.init_depth, rng),
+            7 => config.islands = int(self.islands, rng),
+            8 => config.migration_interval = int(self.migration_interval, rng),
+            _ => config.migrants = int(self.migrants, rng),
+        }
+    }
+}
+
+/// A genetic algorithm over `Config`s, with tournament selection, uniform crossover,
+/// per-gene mutation and elitism.
+pub struct MetaGa {
+    space: SearchSpace,
+    pop_size: usize,
+    generations: usize,
+    pool_size: usize,
+    mutation_rate: f32,
+    gens_per_run: usize,
+    folds: usize,
+    rng: XorShiftRng,
+    history: Vec<(Config, Float)>,
+}
+
+impl Default for MetaGa {
+    fn default() -> MetaGa {
+        MetaGa::new()
+    }
+}
+
+impl MetaGa {
+    /// Returns a `MetaGa` over the default `SearchSpace`, evolving 10 configurations
+    /// for 5 generations, each evaluated by runs of 20 generations on 3 folds.
+    pub fn new() -> MetaGa {
+        MetaGa {
+            space: SearchSpace::default(),
+            pop_size: 10,
+            generations: 5,
+            pool_size: 3,
+            mutation_rate: 0.2,
+            gens_per_run: 20,
+            folds: 3,
+            rng: split_rng(&mut thread_rng()),
+            history: vec![],
+        }
+    }
+
+    pub fn set_search_space(mut self, space: SearchSpace) -> MetaGa {
+        self.space = space;
+        self
+    }
+
+    /// Sets the number of configurations per generation.
+    pub fn set_pop_size(mut self, size: usize) -> MetaGa {
+        if size == 0 {
+            panic!("@MetaGa::set_pop_size() Cannot evolve an empty population.");
+        }
+        self.pop_size = size;
+        self
+    }
+
+    pub fn set_generations(mut self, gens: usize) -> MetaGa {
+        self.generations = gens;
+        self
+    }
+
+    /// Sets the size of the tournaments selecting parents.
+    pub fn set_pool_size(mut self, ps: usize) -> MetaGa {
+        self.pool_size = ps;
+        self
+    }
+
+    /// Sets the probability of each gene of an offspring to be drawn anew.
+    pub fn set_mutation_rate(mut self, rate: f32) -> MetaGa {
+        self.mutation_rate = rate;
+        self
+    }
+
+    /// Sets the number of generations of the runs evaluating a configuration.
+    pub fn set_gens_per_run(mut self, gens: usize) -> MetaGa {
+        self.gens_per_run = gens;
+        self
+    }
+
+    /// Sets the number of folds of the training set a configuration is validated on.
+    pub fn set_folds(mut self, k: usize) -> MetaGa {
+        if k < 2 {
+            panic!("@MetaGa::set_folds() Validation needs at least 2 folds.");
+        }
+        self.folds = k;
+        self
+    }
+
+    /// Seeds the random number generator, from which runs are seeded too.
+    pub fn set_seed(mut self, seed: u64) -> MetaGa {
+        self.rng = rng_from_seed(seed);
+        self
+    }
+
+    /// Returns every configuration evaluated by the last call to `run()`, with its
+    /// validation error, in order of evaluation.
+    pub fn history(&self) -> &[(Config, Float)] {
+        &self.history
+    }
+
+    /// Evolves configurations of `GP` runs on the training set of `data` and returns the
+    /// fittest with its validation error. Runs are made of the `GP`s returned by `gp`
+    /// out of each fold, configured by `Config::apply()`, which is where the
+    /// parameters not tuned (fitness, linear scaling, ...) are set.
+    pub fn run<F>(&mut self, data: &Data, gp: F) -> (Config, Float)
+        where F: Fn(Data) -> GP + Sync
+    {
+        let folds = data.folds(self.folds);
+        self.history.clear();
+        let mut pop: Vec<Config> =
+            (0..self.pop_size).map(|_| self.space.sample(&mut self.rng)).collect();
+        let mut errors = self.evaluate(&pop, &folds, &gp);
+        for _ in 0..self.generations {
+            let elite = fittest(&errors);
+            let mut offspring = vec![pop[elite]];
+            while offspring.len() < self.pop_size {
+                let mother = pop[self.tournament(&errors)];
+                let father = pop[self.tournament(&errors)];
+                offspring.push(self.vary(mother, father));
+            }
+            // the elite is not evaluated again.
+            let mut offspring_errors = vec![errors[elite]];
+            offspring_errors.extend(self.evaluate(&offspring[1..], &folds, &gp));
+            pop = offspring;
+            errors = offspring_errors;
+        }
+        let best = fittest(&errors);
+        (pop[best], errors[best])
+    }
+
+    /// Returns the validation errors of `configs`, evaluated in parallel.
+    fn evaluate<F>(&mut self, configs: &[Config], folds: &[Data], gp: &F) -> Vec<Float>
+        where F: Fn(Data) -> GP + Sync
+    {
+        let gens = self.gens_per_run;
+        // seeds are drawn beforehand, so that results do not depend on scheduling.
+        let seeds: Vec<u64> = configs.iter().map(|_| self.rng.gen()).collect();
+        let errors: Vec<Float> = configs.par_iter()
+            .zip(seeds)
+            .map(|(config, seed)| validation_error(config, folds, gens, seed, gp))
+            .collect();
+        self.history.extend(configs.iter().cloned().zip(errors.iter().cloned()));
+        errors
+    }
+
+    /// Returns the index of the winner of a tournament among the population.
+    fn tournament(&mut self, errors: &[Float]) -> usize {
+        let mut winner = self.rng.gen_range(0, errors.len());
+        for _ in 1..self.pool_size {
+            let rival = self.rng.gen_range(0, errors.len());
+            if errors[rival] < errors[winner] {
+                winner = rival;
+            }
+        }
+        winner
+    }
+
+    /// Returns the uniform crossover of `mother` and `father`, mutated.
+    fn vary(&mut self, mother: Config, father: Config) -> Config {
+        let mut child = mother;
+        if self.rng.gen() {
+            child.pop_size = father.pop_size;
+        }
+        if self.rng.gen() {
+            child.pool_size = father.pool_size;
+        }
+        if self.rng.gen() {
+            child.xo_rate = father.xo_rate;
+        }
+        if self.rng.gen() {
+            child.selection = father.selection;
+        }
+        if self.rng.gen() {
+            child.geometric_semantic = father.geometric_semantic;
+        }
+        if self.rng.gen() {
+            child.mutation_step = father.mutation_step;
+        }
+        if self.rng.gen() {
+            child.init_depth = father.init_depth;
+        }
+        if self.rng.gen() {
+            child.islands = father.islands;
+        }
+        if self.rng.gen() {
+            child.migration_interval = father.migration_interval;
+        }
+        if self.rng.gen() {
+            child.migrants = father.migrants;
+        }
+        for gene in 0..GENES {
+            if self.rng.gen::<f32>() < self.mutation_rate {
+                self.space.draw(&mut child, gene, &mut self.rng);
+            }
+        }
+        child
+    }
+}
+
+/// Returns the mean, over `folds`, of the test error of the fittest individual of a
+/// run of `gens` generations configured by `config`. Infinite if a run yields none.
+fn validation_error<F>(config: &Config, folds: &[Data], gens: usize, seed: u64, gp: &F) -> Float
+    where F: Fn(Data) -> GP
+{
+    let mut rng = rng_from_seed(seed);
+    let mut total = 0.0;
+    for fold in folds {
+        let mut mgp = config.mgp(fold, rng.gen(), gp);
+        mgp.init();
+        mgp.evolve(gens);
+        match mgp.best().and_then(|best| best.test()) {
+            Some(error) if error.is_finite() => total += error,
+            _ => return Float::INFINITY,
+        }
+    }
+    total / folds.len() as Float
+}
+
+/// Returns the index of the lowest error.
+fn fittest(errors: &[Float]) -> usize {
+    (0..errors.len()).min_by(|&a, &b| errors[a].total_cmp(&errors[b])).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::problems;
+
+    fn small_space() -> SearchSpace {
+        SearchSpace {
+            pop_size: (10, 20),
+            init_depth: (2, 3),
+            islands: (1, 2),
+            migration_interval: (1, 2),
+            migrants: (1, 2),
+            ..SearchSpace::default()
+        }
+    }
+
+    #[test]
+    fn sampled_configurations_lie_in_the_search_space() {
+        let space = small_space();
+        let mut rng = rng_from_seed(1);
+        for _ in 0..100 {
+            let c = space.sample(&mut rng);
+            assert!(c.pop_size >= 10 && c.pop_size <= 20);
+            assert!(c.pool_size >= 2 && c.pool_size <= 8);
+            assert!(c.xo_rate >= 0.0 && c.xo_rate <= 1.0);
+            assert!(c.mutation_step >= 0.1 && c.mutation_step <= 2.0);
+            assert!(c.init_depth >= 2 && c.init_depth <= 3);
+            assert!(c.islands >= 1 && c.islands <= 2);
+            assert!(c.migration_interval >= 1 && c.migration_interval <= 2);
+            assert!(c.migrants >= 1 && c.migrants <= 2);
+        }
+    }
+
+    #[test]
+    fn configurations_split_the_population_among_islands() {
+        let data = problems::koza(1).data(&mut rng_from_seed(1));
+        let mut config = small_space().sample(&mut rng_from_seed(1));
+        config.pop_size = 30;
+        config.islands = 3;
+        let mgp = config.mgp(&data, 1, GP::new_gp);
+        assert_eq!(mgp.subpops().len(), 3);
+        assert!(mgp.subpops().iter().all(|gp| gp.pop_size() == 10));
+    }
+
+    #[test]
+    fn the_fittest_configuration_evaluated_is_returned() {
+        let data = problems::koza(1).data(&mut rng_from_seed(1));
+        let mut meta = MetaGa::new()
+            .set_search_space(small_space())
+            .set_pop_size(4)
+            .set_generations(2)
+            .set_gens_per_run(2)
+            .set_folds(2)
+            .set_seed(1);
+        let (config, error) = meta.run(&data, GP::new_gp);
+        // the initial configurations, then 3 offspring per generation besides the elite.
+        assert_eq!(meta.history().len(), 4 + 2 * 3);
+        let lowest = meta.history().iter().map(|h| h.1).fold(Float::INFINITY, Float::min);
+        assert_eq!(error, lowest);
+        assert!(meta.history().contains(&(config, error)));
+    }
+}
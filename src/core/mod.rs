@@ -1,9 +1,30 @@
+/// Floating point type of data, constants, semantics and errors: `f32`, or `f64` when
+/// the crate is built with the `f64` feature.
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+#[cfg(feature = "f64")]
+pub type Float = f64;
+
+pub mod matrix;
 pub mod data;
+pub mod problems;
 
 pub mod utils;
+pub mod fitness;
 
 pub mod node;
+pub mod tree;
+pub mod evaluator;
+pub mod cache;
 pub mod individual;
 pub mod population;
 pub mod gp;
 pub mod multi_gp;
+pub mod distributed;
+pub mod simplification;
+pub mod pruning;
+pub mod constant_optimization;
+pub mod differentiation;
+pub mod classification;
+pub mod ensemble;
+pub mod meta_ga;
@@ -1,91 +1,1264 @@
+use rand::{thread_rng, Rng, XorShiftRng};
+use core::data::Data;
 use core::gp::GP;
 use core::individual::Individual;
+use core::node::Node;
+use core::Float;
+use core::utils::{add_assign, map_assign, rmse, rng_from_seed, sample, split_rng};
 use rayon::prelude::*;
+use std::sync::mpsc::sync_channel;
+use std::mem;
+use std::slice;
+use std::thread;
 
+/// How many individuals emigrate from an island at each migration.
+#[derive(Debug, Clone, Copy)]
+pub enum Migrants {
+    /// A fixed number of individuals.
+    Count(usize),
+    /// A fraction of the population of the island, rounded up.
+    Fraction(f32),
+}
+
+/// Which individuals of an island emigrate. Emigrants are copies: they also remain
+/// in their island.
+#[derive(Debug, Clone, Copy)]
+pub enum Emigration {
+    /// The fittest individuals.
+    Best,
+    /// Distinct individuals drawn uniformly at random.
+    Random,
+    /// Winners of tournaments of the given size.
+    Tournament(usize),
+}
+
+/// Which residents of an island immigrants replace.
+#[derive(Debug, Clone, Copy)]
+pub enum Replacement {
+    /// Immigrants join the island, whose worst individuals (immigrants included)
+    /// are then discarded to get back to its population size.
+    Worst,
+    /// Immigrants replace residents drawn at random.
+    Random,
+    /// Immigrants replace residents drawn at random, sparing the given number
+    /// of fittest residents.
+    RandomSparingElites(usize),
+}
+
+/// Which islands the emigrants of each island go to. Islands are numbered in order
+/// of `add_subpop()`; every destination gets its own copies of the emigrants.
+#[derive(Debug, Clone)]
+pub enum Topology {
+    /// To the next island, the last one sending to the first.
+    Ring,
+    /// To the next and the previous island.
+    BidirectionalRing,
+    /// The given hub island sends to all the others, which send to the hub only.
+    Star(usize),
+    /// To all the other islands.
+    FullyConnected,
+    /// Islands laid out row by row on a grid of the given rows and columns, whose
+    /// edges wrap around, send to their 4 neighbours.
+    Torus(usize, usize),
+    /// To the given number of other islands, drawn at random at every migration.
+    Random(usize),
+    /// Island `i` sends to island `j` when `adjacency[i][j]` holds.
+    Adjacency(Vec<Vec<bool>>),
+}
+
+impl Topology {
+    /// Returns the islands that island `i` of `n` sends migrants to.
+    pub fn destinations<R: Rng>(&self, i: usize, n: usize, rng: &mut R) -> Vec<usize> {
+        let others = (0..n).filter(|&j| j != i);
+        let mut destinations: Vec<usize> = match *self {
+            Topology::Ring => vec![(i + 1) % n],
+            Topology::BidirectionalRing => vec![(i + 1) % n, (i + n - 1) % n],
+            Topology::Star(hub) if i == hub => others.collect(),
+            Topology::Star(hub) => vec![hub],
+            Topology::FullyConnected => others.collect(),
+            Topology::Torus(rows, cols) => {
+                let (r, c) = (i / cols, i % cols);
+                vec![((r + 1) % rows) * cols + c,
+                     ((r + rows - 1) % rows) * cols + c,
+                     r * cols + (c + 1) % cols,
+                     r * cols + (c + cols - 1) % cols]
+            }
+            Topology::Random(k) => {
+                let k = k.min(n - 1);
+                sample(rng, others, k)
+            }
+            Topology::Adjacency(ref adjacency) => others.filter(|&j| adjacency[i][j]).collect(),
+        };
+        // small rings and grids have repeated neighbours, or are their own.
+        destinations.sort();
+        destinations.dedup();
+        destinations.retain(|&j| j != i);
+        destinations
+    }
+
+    /// Panics if the topology is malformed, whatever the number of islands.
+    fn check_shape(&self) {
+        let well_formed = match *self {
+            Topology::Torus(rows, cols) => rows > 0 && cols > 0,
+            Topology::Adjacency(ref adjacency) => {
+                adjacency.iter().all(|row| row.len() == adjacency.len())
+            }
+            _ => true,
+        };
+        if !well_formed {
+            panic!("@Mgp::set_topology() {:?} is malformed.", self);
+        }
+    }
+
+    /// Returns whether the topology fits `n` islands.
+    pub fn fits(&self, n: usize) -> bool {
+        match *self {
+            Topology::Star(hub) => hub < n,
+            Topology::Torus(rows, cols) => rows * cols == n,
+            Topology::Adjacency(ref adjacency) => {
+                adjacency.len() == n && adjacency.iter().all(|row| row.len() == n)
+            }
+            _ => true,
+        }
+    }
+
+    /// Panics if the topology does not fit `n` islands, naming `caller`.
+    fn check(&self, n: usize, caller: &str) {
+        if !self.fits(n) {
+            panic!("@Mgp::{}() {:?} does not fit {} islands.", caller, self, n);
+        }
+    }
+}
+
+/// State of an island at some generation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IslandStats {
+    /// Generations evolved by the island, as counted by its `Mgp`.
+    pub generation: usize,
+    /// Training error of the fittest individual.
+    pub best: Option<Float>,
+    /// Mean of the finite training errors, `None` if there are none.
+    pub mean: Option<Float>,
+    /// Semantic diversity: mean distance (as the root mean squared difference) of the
+    /// training semantics of individuals to their centroid, over finite semantics.
+    pub diversity: Float,
+}
+
+impl IslandStats {
+    /// Computes the statistics of `gp`, whose population is sorted.
+    pub fn of(gp: &GP, generation: usize) -> IslandStats {
+        let pop = gp.pop().core();
+        let errors: Vec<Float> =
+            pop.iter().filter_map(|i| i.train()).filter(|e| e.is_finite()).collect();
+        let mean = if errors.is_empty() {
+            None
+        } else {
+            Some(errors.iter().sum::<Float>() / errors.len() as Float)
+        };
+        let semantics: Vec<Vec<Float>> = pop.iter()
+            .filter(|i| i.has_finite_semantics())
+            .map(|i| i.train_semantics())
+            .collect();
+        let diversity = match semantics.first() {
+            Some(first) => {
+                let n = semantics.len() as Float;
+                let mut centroid = vec![0.0; first.len()];
+                for s in &semantics {
+                    add_assign(&mut centroid, s);
+                }
+                map_assign(&mut centroid, |c| c / n);
+                semantics.iter().map(|s| rmse(s, &centroid)).sum::<Float>() / n
+            }
+            None => 0.0,
+        };
+        IslandStats {
+            generation,
+            best: pop.first().and_then(|i| i.train()),
+            mean,
+            diversity,
+        }
+    }
+}
+
+/// An individual that moved from one island to another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    /// Generation of the destination island at arrival, as counted by its `Mgp`.
+    pub generation: usize,
+    pub from: usize,
+    pub to: usize,
+    /// Size of the migrant's program.
+    pub size: usize,
+    /// Training error in the destination island, `None` if it was refused (see
+    /// `GP::adopt()`).
+    pub train: Option<Float>,
+    /// Whether it made it into the population of the destination island.
+    pub survived: bool,
+}
+
+impl Migration {
+    /// Returns the records of `arrivals`, as returned by `immigrate()`.
+    fn records(generation: usize,
+               from: usize,
+               to: usize,
+               arrivals: Vec<(usize, Option<Float>, bool)>)
+               -> Vec<Migration> {
+        arrivals.into_iter()
+            .map(|(size, train, survived)| {
+                Migration {
+                    generation,
+                    from,
+                    to,
+                    size,
+                    train,
+                    survived,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Which training instances each island evolves on, out of the `Data` of its `GP`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowPartition {
+    /// All of them.
+    Shared,
+    /// A bootstrap sample of its own (see `Data::bootstrap()`).
+    Bootstrap,
+    /// A contiguous block of its own, disjoint from those of the other islands
+    /// (see `Data::shards()`).
+    Shards,
+}
+
+/// Which input variables each island evolves on, out of the `Data` of its `GP`.
+///
+/// Migrants are translated to the variables of their destination, which refuses those
+/// reading a variable it lacks: islands with disjoint sets of variables refuse every
+/// migrant from each other that reads any input at all.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeaturePartition {
+    /// All of them.
+    All,
+    /// A random subset of the given size of its own.
+    Random(usize),
+    /// The given variables, per island.
+    Given(Vec<Vec<usize>>),
+}
+
+/// A multi-population (island model) GP: `GP` subpopulations evolve in parallel and
+/// exchange individuals at migration instants, along the edges of a `Topology`
+/// (by default a ring). Islands may mix standard GP and GSGP (hybrid GP).
 pub struct Mgp {
     core: Vec<GP>,
+    /// The `Data` each island was added with, which partitions are taken from.
+    datasets: Vec<Data>,
+    rows: RowPartition,
+    features: FeaturePartition,
+    /// Input variables of each island, as indices into the inputs of its `datasets`,
+    /// `None` for all of them.
+    island_features: Vec<Option<Vec<usize>>>,
     pop_size: usize,
+    migrants: Migrants,
+    emigration: Emigration,
+    replacement: Replacement,
+    topology: Topology,
+    /// Generations between emigrations of each island, `None` for `default_interval`.
+    intervals: Vec<Option<usize>>,
+    default_interval: usize,
+    /// Largest program reconstructed for a migrant without a tree (see `GP::adopt()`).
+    reconstruction_limit: usize,
+    /// Batches of migrants an island's mailbox holds in `evolve_async()`.
+    mailbox_capacity: usize,
+    rng: XorShiftRng,
+    /// Generations evolved since `init()`.
+    generation: usize,
+    /// Statistics of each island, recorded at every migration instant and at the end of
+    /// every call to an `evolve` method.
+    history: Vec<Vec<IslandStats>>,
+    migrations: Vec<Migration>,
+}
+
+impl Default for Mgp {
+    fn default() -> Mgp {
+        Mgp::new()
+    }
 }
 
 impl Mgp {
-    /// Returns a blank `Mgp` to be configured
+    /// Returns a blank `Mgp` to be configured.
+    /// It defaults to 4 emigrants, the best, replacing the worst individuals of
+    /// the next island in a ring every 30 generations.
     pub fn new() -> Mgp {
         Mgp {
             core: vec![],
+            datasets: vec![],
+            rows: RowPartition::Shared,
+            features: FeaturePartition::All,
+            island_features: vec![],
             pop_size: 0,
+            migrants: Migrants::Count(4),
+            emigration: Emigration::Best,
+            replacement: Replacement::Worst,
+            topology: Topology::Ring,
+            intervals: vec![],
+            default_interval: 30,
+            reconstruction_limit: 10_000,
+            mailbox_capacity: 4,
+            rng: split_rng(&mut thread_rng()),
+            generation: 0,
+            history: vec![],
+            migrations: vec![],
         }
     }
 
     /// Adds a `GP` subpopulation.
     pub fn add_subpop(mut self, s: GP) -> Mgp {
+        self.datasets.push(s.data().clone());
         self.core.push(s);
+        self.intervals.push(None);
+        self.history.push(vec![]);
         self
     }
 
+    /// Sets how many individuals emigrate from each island at each migration.
+    pub fn set_migrants(mut self, migrants: Migrants) -> Mgp {
+        self.migrants = migrants;
+        self
+    }
+
+    /// Picks which individuals emigrate, according to the options provided by
+    /// `enum Emigration`.
+    pub fn set_emigration(mut self, emigration: Emigration) -> Mgp {
+        self.emigration = emigration;
+        self
+    }
+
+    /// Picks which residents immigrants replace, according to the options provided by
+    /// `enum Replacement`.
+    pub fn set_replacement(mut self, replacement: Replacement) -> Mgp {
+        self.replacement = replacement;
+        self
+    }
+
+    /// Sets which islands send migrants to which, according to the options provided by
+    /// `enum Topology`. Whether it fits the number of islands is checked by `init()`.
+    pub fn set_topology(mut self, topology: Topology) -> Mgp {
+        topology.check_shape();
+        self.topology = topology;
+        self
+    }
+
+    /// Sets which training instances each island evolves on, out of the `Data` of its
+    /// `GP`. Partitions are drawn by `init()`.
+    pub fn set_row_partition(mut self, rows: RowPartition) -> Mgp {
+        self.rows = rows;
+        self
+    }
+
+    /// Sets which input variables each island evolves on, out of the `Data` of its `GP`.
+    /// Partitions are drawn by `init()`.
+    pub fn set_feature_partition(mut self, features: FeaturePartition) -> Mgp {
+        self.features = features;
+        self
+    }
+
+    /// Sets the number of generations between emigrations of the islands without an
+    /// interval of their own. `0` means no migration.
+    pub fn set_migration_interval(mut self, gens: usize) -> Mgp {
+        self.default_interval = gens;
+        self
+    }
+
+    /// Sets the number of generations between emigrations of island `island`
+    /// (in order of `add_subpop()`). `0` means that it never sends migrants.
+    pub fn set_island_migration_interval(mut self, island: usize, gens: usize) -> Mgp {
+        if island >= self.core.len() {
+            panic!("@Mgp::set_island_migration_interval() There is no island {}.", island);
+        }
+        self.intervals[island] = Some(gens);
+        self
+    }
+
+    /// Sets the largest program, in nodes, reconstructed for a migrant from an island
+    /// under `Variation::GeometricSemantic` into one under `Variation::Standard`.
+    /// Larger migrants are not admitted. Defaults to 10000.
+    pub fn set_reconstruction_limit(mut self, nodes: usize) -> Mgp {
+        self.reconstruction_limit = nodes;
+        self
+    }
+
+    /// Sets how many batches of migrants the mailbox of each island holds in
+    /// `evolve_async()`. Defaults to 4.
+    pub fn set_mailbox_capacity(mut self, batches: usize) -> Mgp {
+        if batches == 0 {
+            panic!("@Mgp::set_mailbox_capacity() Mailboxes must hold at least one batch.");
+        }
+        self.mailbox_capacity = batches;
+        self
+    }
+
+    /// Seeds the random number generator used for migration. Islands have their own,
+    /// see `GP::set_seed()`.
+    pub fn set_seed(mut self, seed: u64) -> Mgp {
+        self.rng = rng_from_seed(seed);
+        self
+    }
+
+    /// Returns the `GP` subpopulations.
+    pub fn subpops(&self) -> &Vec<GP> {
+        &self.core
+    }
+
+    /// Returns the total number of individuals of the subpopulations, as of `init()`.
+    pub fn pop_size(&self) -> usize {
+        self.pop_size
+    }
+
+    /// Returns the number of generations evolved since `init()`.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Returns the fittest individual across all islands, if any. With partitioned
+    /// data, errors are those on the data of each island.
+    pub fn best(&self) -> Option<&Individual> {
+        self.core
+            .iter()
+            .filter(|gp| !gp.pop().is_empty())
+            .map(|gp| gp.pop().get_first())
+            .min_by(|a, b| a.cmp_train(b))
+    }
+
+    /// Returns the fittest individual of each island as a program over the inputs of
+    /// the `Data` the island was added with (GSGP individuals reconstructed), evaluated
+    /// on that `Data` as the island would. `None` for those without a tree over
+    /// `max_size` nodes.
+    pub fn island_bests(&self, max_size: usize) -> Vec<Option<Individual>> {
+        self.core
+            .iter()
+            .enumerate()
+            .map(|(i, gp)| {
+                let features = self.island_features.get(i).and_then(|f| f.as_deref());
+                let best = gp.pop().core().first()?;
+                let (mut programs, _) = translate(slice::from_ref(best), features, None, max_size);
+                let mut best = programs.pop()?;
+                best.compute_semantics(&self.datasets[i]);
+                gp.evaluate_on(&mut best, &self.datasets[i]);
+                Some(best)
+            })
+            .collect()
+    }
+
+    /// Returns the statistics of each island over time, indexed as `[island][record]`.
+    /// They are recorded after `init()`, at every migration instant (before migrants
+    /// leave) and at the end of every call to an `evolve` method.
+    pub fn history(&self) -> &[Vec<IslandStats>] {
+        &self.history
+    }
+
+    /// Returns every individual that moved between islands, in order of arrival.
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
     /// Initializes `Mgp` by calling initialization on its subpopulations
     ///
-    /// Also, it sets the size of `Mgp` to the total of its constituent subpopulations.
+    /// Also, it sets the size of `Mgp` to the total of its constituent subpopulations,
+    /// and starts over its history.
+    /// Panics if the topology does not fit the number of subpopulations.
     pub fn init(&mut self) {
-        let total_size = self.core.par_iter().map(|gp| gp.pop().size()).sum();
-        self.pop_size = total_size;
+        self.topology.check(self.core.len(), "init");
+        self.partition();
         self.core.par_iter_mut().for_each(|gp| gp.init_new_pop());
+        self.pop_size = self.core.par_iter().map(|gp| gp.pop().size()).sum();
+        self.generation = 0;
+        self.migrations.clear();
+        for (gp, history) in self.core.iter().zip(&mut self.history) {
+            *history = vec![IslandStats::of(gp, 0)];
+        }
     }
 
-    /// Evolves all the subpopulations for the specified number of `turns`
-    /// where each turn lasts `gens_per_turn` generations. At the end of each turn,
-    /// except for the last, a migration takes place (under a best-to-worst policy).
-    /// All subpopulations are evolved in parallel and synchronize at migration instants.
+    /// Evolves all the subpopulations for `turns` turns of `gens_per_turn` generations,
+    /// every island migrating at the end of each turn except the last.
+    /// For this call, `gens_per_turn` takes the place of the migration intervals,
+    /// which are left as configured; see `evolve()`.
     pub fn evolve_in_parallel(&mut self, turns: u8, gens_per_turn: usize) {
-        for _ in 1..turns {
-            // evolve for a while and then stop
+        let intervals = vec![gens_per_turn; self.core.len()];
+        self.evolve_every(turns as usize * gens_per_turn, &intervals);
+    }
+
+    /// Evolves all the subpopulations for `gens` generations. Each island sends
+    /// migrants whenever the number of generations so far is a multiple of its
+    /// migration interval, except at the end.
+    /// All subpopulations are evolved in parallel and synchronize at migration instants.
+    pub fn evolve(&mut self, gens: usize) {
+        let intervals: Vec<usize> = (0..self.core.len()).map(|i| self.interval(i)).collect();
+        self.evolve_every(gens, &intervals);
+    }
+
+    /// `evolve()`, with the given migration interval of each island.
+    fn evolve_every(&mut self, gens: usize, intervals: &[usize]) {
+        let mut gen = 0;
+        while gen < gens {
+            // the next generation at which some island emigrates, if before the end.
+            let next = intervals.iter()
+                .cloned()
+                .filter(|&k| k > 0)
+                .map(|k| (gen / k + 1) * k)
+                .min()
+                .map_or(gens, |next| next.min(gens));
             self.core
                 .par_iter_mut()
-                .for_each(|gp| gp.evolve(gens_per_turn));
-            {
-                // collect migrants and migrate in a ring pattern
-                // we have subpops A, B and C. So migration goes like
-                // A -> B; B -> C; and C -> A
-                let migrants: Vec<Vec<Individual>> =
-                    self.core.par_iter().map(|gp| gp.pop().clone_k_best(4)).collect();
-
-                migrants
-                    .par_iter()
-                    .take(migrants.len() - 1)
-                    .zip(self.core.par_iter_mut().skip(1))
-                    .for_each(|(mgrnts, gp)| gp.pop_mut().add_individuals(mgrnts.to_vec()));
-
-                // last migration
-                self.core[0]
-                    .pop_mut()
-                    .add_individuals(migrants[migrants.len() - 1].to_vec());
+                .for_each(|gp| gp.evolve(next - gen));
+            self.generation += next - gen;
+            gen = next;
+            for (gp, history) in self.core.iter().zip(&mut self.history) {
+                history.push(IslandStats::of(gp, self.generation));
+            }
+            if gen < gens {
+                self.migrate(gen, intervals);
             }
-            // re-adjust subpopulation sizes.
-            self.core.par_iter_mut().for_each(|gp| gp.clean());
         }
-        // evolve for the last time without migration
-        self.core
-            .par_iter_mut()
-            .for_each(|gp| gp.evolve(gens_per_turn));
-        // only returns best individual with some other method!
+    }
+
+    /// Evolves all the subpopulations for `gens` generations asynchronously: each island
+    /// runs on its own thread and, whenever its own migration interval elapses (except at
+    /// the end), sends emigrants to the mailboxes of its destinations and then takes in
+    /// the immigrants waiting in its own mailbox, without waiting for the other islands.
+    ///
+    /// Mailboxes hold at most `set_mailbox_capacity()` batches of migrants; a batch sent
+    /// to a full mailbox is lost. An island that never emigrates never takes in immigrants
+    /// either. As arrivals depend on the speed of the islands, runs are not reproducible
+    /// even with seeds.
+    pub fn evolve_async(&mut self, gens: usize) {
+        let n = self.core.len();
+        self.topology.check(n, "evolve_async");
+        let (senders, mailboxes): (Vec<_>, Vec<_>) =
+            (0..n).map(|_| sync_channel(self.mailbox_capacity)).unzip();
+        let intervals: Vec<usize> = (0..n).map(|i| self.interval(i)).collect();
+        let rngs: Vec<XorShiftRng> = (0..n).map(|_| split_rng(&mut self.rng)).collect();
+        let (migrants, emigration) = (self.migrants, self.emigration);
+        let (replacement, limit) = (self.replacement, self.reconstruction_limit);
+        let (topology, base) = (&self.topology, self.generation);
+        let data: Vec<Data> = self.core.iter().map(|gp| gp.data().clone()).collect();
+        let data = &data;
+        let (partitioned, features) = (self.partitioned(), &self.island_features);
+        let islands = self.core.iter_mut().zip(mailboxes).zip(rngs).enumerate();
+        let logs: Vec<(Vec<IslandStats>, Vec<Migration>)> = thread::scope(|scope| {
+            let handles: Vec<_> = islands.map(|(i, ((gp, mailbox), mut rng))| {
+                    let senders = senders.clone();
+                    let interval = intervals[i];
+                    scope.spawn(move || {
+                        let (mut history, mut migrations) = (vec![], vec![]);
+                        let mut gen = 0;
+                        while gen < gens {
+                            let next =
+                                if interval > 0 { (gen + interval).min(gens) } else { gens };
+                            gp.evolve(next - gen);
+                            gen = next;
+                            history.push(IslandStats::of(gp, base + gen));
+                            if gen == gens {
+                                break;
+                            }
+                            let batch = emigrants(gp, migrants, emigration, &mut rng);
+                            for destination in topology.destinations(i, n, &mut rng) {
+                                let batch = if partitioned {
+                                    let (batch, refused) = translate(&batch,
+                                                                     features[i].as_deref(),
+                                                                     features[destination]
+                                                                         .as_deref(),
+                                                                     limit);
+                                    let refused = refused.into_iter()
+                                        .map(|size| (size, None, false))
+                                        .collect();
+                                    migrations.extend(Migration::records(base + gen,
+                                                                         i,
+                                                                         destination,
+                                                                         refused));
+                                    batch
+                                } else {
+                                    batch.clone()
+                                };
+                                // the batch is lost if the mailbox is full, or the island done.
+                                let _ = senders[destination].try_send((i, batch));
+                            }
+                            for (from, immigrants) in mailbox.try_iter() {
+                                let origin = &data[from];
+                                let arrivals = immigrate(gp,
+                                                         immigrants,
+                                                         origin,
+                                                         replacement,
+                                                         limit,
+                                                         &mut rng);
+                                migrations.extend(Migration::records(base + gen,
+                                                                     from,
+                                                                     i,
+                                                                     arrivals));
+                            }
+                        }
+                        (history, migrations)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        for (i, (history, migrations)) in logs.into_iter().enumerate() {
+            self.history[i].extend(history);
+            self.migrations.extend(migrations);
+        }
+        self.migrations.sort_by_key(|m| m.generation);
+        self.generation += gens;
+    }
+
+    /// Gives every island its partition of the `Data` it was added with.
+    fn partition(&mut self) {
+        let n = self.core.len();
+        if let FeaturePartition::Given(ref features) = self.features {
+            if features.len() != n {
+                panic!("@Mgp::init() Features are given for {} of {} islands.",
+                       features.len(),
+                       n);
+            }
+        }
+        let mut datasets = vec![];
+        self.island_features.clear();
+        for (i, data) in self.datasets.iter().enumerate() {
+            let data = match self.rows {
+                RowPartition::Shared => data.clone(),
+                RowPartition::Bootstrap => data.bootstrap(&mut self.rng),
+                RowPartition::Shards => data.shards(n).swap_remove(i),
+            };
+            let features = match self.features {
+                FeaturePartition::All => None,
+                FeaturePartition::Random(k) => {
+                    let mut features = sample(&mut self.rng, 0..data.dims(), k.max(1));
+                    features.sort();
+                    Some(features)
+                }
+                FeaturePartition::Given(ref features) => Some(features[i].clone()),
+            };
+            datasets.push(match features {
+                Some(ref features) => data.select_features(features),
+                None => data,
+            });
+            self.island_features.push(features);
+        }
+        let core = mem::take(&mut self.core);
+        self.core = core.into_iter().zip(datasets).map(|(gp, data)| gp.set_data(data)).collect();
+    }
+
+    /// Whether islands evolve on different data, so that migrants must be re-evaluated
+    /// from their programs.
+    fn partitioned(&self) -> bool {
+        self.rows != RowPartition::Shared || self.features != FeaturePartition::All
+    }
+
+    /// Generations between emigrations of island `i`.
+    fn interval(&self, i: usize) -> usize {
+        self.intervals[i].unwrap_or(self.default_interval)
+    }
+
+    /// Moves migrants from every island due at generation `gen`, according to its
+    /// migration interval in `intervals`, to its destinations in the topology.
+    /// Emigrants are all chosen before any island receives immigrants.
+    fn migrate(&mut self, gen: usize, intervals: &[usize]) {
+        let n = self.core.len();
+        self.topology.check(n, "migrate");
+        let mut moves = vec![];
+        for (i, &k) in intervals.iter().enumerate() {
+            if k > 0 && gen.is_multiple_of(k) {
+                let batch = emigrants(&self.core[i], self.migrants, self.emigration, &mut self.rng);
+                for destination in self.topology.destinations(i, n, &mut self.rng) {
+                    let (batch, refused) = if self.partitioned() {
+                        translate(&batch,
+                                  self.island_features[i].as_deref(),
+                                  self.island_features[destination].as_deref(),
+                                  self.reconstruction_limit)
+                    } else {
+                        (batch.clone(), vec![])
+                    };
+                    moves.push((i, destination, batch, refused));
+                }
+            }
+        }
+        for (from, to, immigrants, refused) in moves {
+            let origin = self.core[from].data().clone();
+            let mut arrivals = immigrate(&mut self.core[to],
+                                         immigrants,
+                                         &origin,
+                                         self.replacement,
+                                         self.reconstruction_limit,
+                                         &mut self.rng);
+            arrivals.extend(refused.into_iter().map(|size| (size, None, false)));
+            self.migrations.extend(Migration::records(self.generation, from, to, arrivals));
+        }
     }
 }
-/*
 
-let mut mgp = vec![gp1, gp2];
+/// Returns copies of the emigrants of `gp`, according to `migrants` and `emigration`.
+/// Assumes its population is sorted.
+pub fn emigrants<R: Rng>(gp: &GP,
+                     migrants: Migrants,
+                     emigration: Emigration,
+                     rng: &mut R)
+                     -> Vec<Individual> {
+    let pop = gp.pop();
+    let n = match migrants {
+        Migrants::Count(n) => n,
+        Migrants::Fraction(f) => (pop.size() as f32 * f).ceil() as usize,
+    }
+    .min(pop.size());
+    match emigration {
+        Emigration::Best => pop.clone_k_best(n),
+        Emigration::Random => {
+            sample(rng, pop.core().iter(), n).into_iter().cloned().collect()
+        }
+        Emigration::Tournament(size) => {
+            (0..n).map(|_| pop.tournament_select(size, rng).clone()).collect()
+        }
+    }
+}
 
-mgp.par_iter_mut()
-   .for_each(|gp| gp.evolve(25)); // works until here.
-{
-let migrants: Vec<Vec<Individual>> = mgp.iter()
-                                    .map(|gp| gp.pop().clone_k_best(4))
-                                    .collect();
+/// Returns the programs of `migrants`, from an island over input variables `from` to
+/// one over input variables `to` (`None` for all of them), as `Individual`s to be
+/// evaluated, with the sizes of the migrants that cannot be sent: those without a tree
+/// over `reconstruction_limit` nodes (see `Individual::reconstruct()`), or using
+/// variables that the destination lacks.
+fn translate(migrants: &[Individual],
+             from: Option<&[usize]>,
+             to: Option<&[usize]>,
+             reconstruction_limit: usize)
+             -> (Vec<Individual>, Vec<usize>) {
+    let (mut translated, mut refused) = (vec![], vec![]);
+    for m in migrants {
+        let program = m.reconstruct(reconstruction_limit).and_then(|ind| {
+            ind.core()
+                .iter()
+                .map(|node| match *node {
+                    Node::Input(k) => {
+                        let feature = from.map_or(k, |from| from[k]);
+                        let local = match to {
+                            Some(to) => to.iter().position(|&f| f == feature),
+                            None => Some(feature),
+                        };
+                        local.map(Node::Input)
+                    }
+                    ref node => Some(node.clone()),
+                })
+                .collect::<Option<Vec<Node>>>()
+        });
+        match program {
+            Some(program) => translated.push(Individual::from_core(program)),
+            None => refused.push(m.size()),
+        }
+    }
+    (translated, refused)
+}
 
-mgp.par_iter_mut()
-   .zip(migrants.par_iter())
-   .for_each(|(gp, migrants)| gp.pop_mut().add_individuals(migrants.to_vec()));
+/// Places `immigrants` from an island evolving on `origin` into `gp`, according to
+/// `replacement`, and sorts its population. Immigrants are first adopted by `gp`
+/// (see `GP::adopt()`), which may refuse some.
+///
+/// Returns, for each immigrant, its size, its training error in `gp` (`None` if refused)
+/// and whether it made it into the population.
+pub fn immigrate<R: Rng>(gp: &mut GP,
+                         immigrants: Vec<Individual>,
+                         origin: &Data,
+                         replacement: Replacement,
+                         reconstruction_limit: usize,
+                         rng: &mut R)
+                         -> Vec<(usize, Option<Float>, bool)> {
+    let mut arrivals = vec![];
+    let mut adopted = vec![];
+    for m in immigrants {
+        let size = m.size();
+        match gp.adopt(m, origin, reconstruction_limit) {
+            Some(ind) => {
+                arrivals.push((size, ind.train(), false));
+                adopted.push((arrivals.len() - 1, ind));
+            }
+            None => arrivals.push((size, None, false)),
+        }
+    }
+    match replacement {
+        Replacement::Worst => {
+            // survivors are the fittest of residents and immigrants, as kept by `clean()`.
+            let residents = gp.pop().size();
+            let mut order: Vec<usize> = (0..residents + adopted.len()).collect();
+            {
+                let individual = |j: usize| if j < residents {
+                    &gp.pop().core()[j]
+                } else {
+                    &adopted[j - residents].1
+                };
+                order.sort_by(|&a, &b| individual(a).cmp_train(individual(b)));
+            }
+            for &j in order.iter().take(gp.pop_size()) {
+                if j >= residents {
+                    arrivals[adopted[j - residents].0].2 = true;
+                }
+            }
+            gp.pop_mut().add_individuals(adopted.into_iter().map(|(_, ind)| ind).collect());
+            gp.clean();
+        }
+        Replacement::Random | Replacement::RandomSparingElites(_) => {
+            let spared = match replacement {
+                Replacement::RandomSparingElites(k) => k,
+                _ => 0,
+            };
+            let pop = gp.pop_mut();
+            let candidates = spared.min(pop.size())..pop.size();
+            let slots = sample(rng, candidates, adopted.len());
+            for (slot, (arrival, immigrant)) in slots.into_iter().zip(adopted) {
+                pop.core_mut()[slot] = immigrant;
+                arrivals[arrival].2 = true;
+            }
+            pop.sort_by_te();
+        }
+    }
+    arrivals
 }
 
-// par iter mut to clean population.
-mgp.par_iter_mut().for_each(|gp| gp.clean());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::individual::variation::{geometric_semantic, standard};
+    use core::node::Node;
+    use core::problems;
+    use core::Float;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn islands(n: usize) -> Mgp {
+        let data = problems::koza(1).data(&mut rng_from_seed(1));
+        (0..n).fold(Mgp::new().set_seed(1), |mgp, i| {
+            mgp.add_subpop(GP::new_gp(data.clone()).set_pop_size(20).set_seed(i as u64))
+        })
+    }
+
+    fn programs(gp: &GP) -> Vec<String> {
+        gp.pop().core().iter().map(|i| format!("{:?}", i.core())).collect()
+    }
+
+    #[test]
+    fn evolving_in_turns_keeps_the_configured_intervals() {
+        let mut mgp = islands(2).set_migration_interval(3).set_island_migration_interval(1, 0);
+        mgp.init();
+        mgp.evolve_in_parallel(2, 2);
+        assert_eq!((mgp.interval(0), mgp.interval(1)), (3, 0));
+        assert_eq!(mgp.subpops()[0].pop().size(), 20);
+    }
+
+    #[test]
+    fn emigrants_are_counted_and_chosen_as_configured() {
+        let mut mgp = islands(1);
+        mgp.init();
+        let (gp, mut rng) = (&mgp.subpops()[0], rng_from_seed(1));
+        let best = gp.pop().clone_k_best(4);
+        let chosen = emigrants(gp, Migrants::Fraction(0.2), Emigration::Best, &mut rng);
+        assert_eq!(format!("{:?}", chosen), format!("{:?}", best));
+        let chosen = emigrants(gp, Migrants::Count(50), Emigration::Random, &mut rng);
+        assert_eq!(chosen.len(), 20);
+    }
+
+    #[test]
+    fn immigrants_spare_the_elites() {
+        let mut mgp = islands(2);
+        mgp.init();
+        let elites = programs(&mgp.subpops()[1])[..3].to_vec();
+        let immigrants = mgp.subpops()[0].pop().clone_k_best(17);
+        let expected: Vec<String> =
+            immigrants.iter().map(|i| format!("{:?}", i.core())).collect();
+        let origin = mgp.subpops()[0].data().clone();
+        let spare = Replacement::RandomSparingElites(3);
+        immigrate(&mut mgp.core[1], immigrants, &origin, spare, 10_000, &mut rng_from_seed(1));
+
+        let mut residents = programs(&mgp.subpops()[1]);
+        assert_eq!(residents.len(), 20);
+        // the 3 fittest residents stay, every other one is replaced.
+        for program in elites.iter().chain(&expected) {
+            let at = residents.iter().position(|r| r == program).unwrap();
+            residents.remove(at);
+        }
+        assert!(residents.is_empty());
+    }
+
+    /// Returns the destinations of each of `n` islands.
+    fn destinations(topology: Topology, n: usize) -> Vec<Vec<usize>> {
+        let mut rng = rng_from_seed(1);
+        (0..n).map(|i| topology.destinations(i, n, &mut rng)).collect()
+    }
+
+    #[test]
+    fn rings_send_to_their_neighbours() {
+        assert_eq!(destinations(Topology::Ring, 4), vec![vec![1], vec![2], vec![3], vec![0]]);
+        assert_eq!(destinations(Topology::BidirectionalRing, 4),
+                   vec![vec![1, 3], vec![0, 2], vec![1, 3], vec![0, 2]]);
+        // with two islands, both neighbours are the same one.
+        assert_eq!(destinations(Topology::BidirectionalRing, 2), vec![vec![1], vec![0]]);
+        assert_eq!(destinations(Topology::Ring, 1), vec![Vec::<usize>::new()]);
+    }
+
+    #[test]
+    fn stars_and_complete_graphs_send_to_all_others() {
+        assert_eq!(destinations(Topology::Star(1), 3), vec![vec![1], vec![0, 2], vec![1]]);
+        assert_eq!(destinations(Topology::FullyConnected, 3),
+                   vec![vec![1, 2], vec![0, 2], vec![0, 1]]);
+    }
+
+    #[test]
+    fn grids_send_to_their_wrapped_neighbours() {
+        // 0 1 2
+        // 3 4 5
+        let grid = destinations(Topology::Torus(2, 3), 6);
+        assert_eq!(grid[0], vec![1, 2, 3]);
+        assert_eq!(grid[4], vec![1, 3, 5]);
+        assert_eq!(grid[5], vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn random_topologies_send_to_distinct_other_islands() {
+        let mut rng = rng_from_seed(1);
+        for i in 0..5 {
+            for _ in 0..20 {
+                let mut destinations = Topology::Random(2).destinations(i, 5, &mut rng);
+                assert_eq!(destinations.len(), 2);
+                assert!(!destinations.contains(&i));
+                destinations.dedup();
+                assert_eq!(destinations.len(), 2);
+            }
+        }
+        // at most all the other islands.
+        assert_eq!(destinations(Topology::Random(5), 3), vec![vec![1, 2], vec![0, 2], vec![0, 1]]);
+    }
+
+    #[test]
+    fn custom_topologies_follow_the_adjacency_matrix() {
+        let adjacency = vec![vec![true, true, false], vec![false, false, false],
+                             vec![true, true, false]];
+        assert_eq!(destinations(Topology::Adjacency(adjacency), 3),
+                   vec![vec![1], vec![], vec![0, 1]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "@Mgp::init()")]
+    fn topologies_that_do_not_fit_are_refused_at_init() {
+        islands(5).set_topology(Topology::Torus(2, 3)).init();
+    }
+
+    #[test]
+    #[should_panic(expected = "@Mgp::set_topology()")]
+    fn malformed_topologies_are_refused() {
+        islands(2).set_topology(Topology::Adjacency(vec![vec![false, true], vec![false]]));
+    }
 
-*/
+    /// Returns initialized standard GP and GSGP islands on `data`, the latter evolved
+    /// for a few generations so that its individuals have no tree.
+    fn hybrid(data: &Data) -> (GP, GP) {
+        let mut standard = GP::new_gp(data.clone()).set_pop_size(20).set_seed(1);
+        let mut gsgp = GP::new_gsgp(data.clone()).set_pop_size(20).set_seed(2);
+        standard.init_new_pop();
+        gsgp.init_new_pop();
+        gsgp.evolve(3);
+        (standard, gsgp)
+    }
+
+    fn assert_close(a: &[Float], b: &[Float]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() <= 1e-3 * x.abs().max(1.0), "{} != {}", x, y);
+        }
+    }
+
+    #[test]
+    fn standard_migrants_become_gsgp_parents() {
+        let data = problems::koza(1).data(&mut rng_from_seed(1));
+        let (standard, gsgp) = hybrid(&data);
+        let migrant = standard.pop().get_first().clone();
+        let adopted = gsgp.adopt(migrant.clone(), standard.data(), 10_000).unwrap();
+        assert_eq!(adopted.semantics(), migrant.semantics());
+        assert_eq!(adopted.train(), migrant.train());
+
+        let resident = gsgp.pop().get_first();
+        assert!(resident.core().is_empty());
+        let mut rng = rng_from_seed(3);
+        let offspring =
+            geometric_semantic::crossover(&adopted, resident, &data, gsgp.fitness(), &mut rng);
+        let mut program = offspring.reconstruct(usize::MAX).unwrap();
+        program.compute_semantics(&data);
+        assert_close(&program.train_semantics(), &offspring.train_semantics());
+    }
+
+    #[test]
+    fn gsgp_migrants_become_standard_parents() {
+        let data = problems::koza(1).data(&mut rng_from_seed(1));
+        let (standard, gsgp) = hybrid(&data);
+        let migrant = gsgp.pop().get_first().clone();
+        assert!(migrant.core().is_empty());
+        assert!(standard.adopt(migrant.clone(), gsgp.data(), 1).is_none());
+
+        let adopted = standard.adopt(migrant.clone(), gsgp.data(), usize::MAX).unwrap();
+        assert!(!adopted.core().is_empty());
+        assert_close(&adopted.train_semantics(), &migrant.train_semantics());
+        let mut rng = rng_from_seed(3);
+        let offspring = standard::mutation(&adopted, &data, standard.fitness(), None, &mut rng);
+        assert!(!offspring.core().is_empty());
+        assert!(offspring.has_semantics_for(&data));
+    }
+
+    #[test]
+    fn gsgp_migrants_keep_their_semantics_only_on_the_same_data() {
+        let data = problems::koza(1).data(&mut rng_from_seed(1));
+        let (_, gsgp) = hybrid(&data);
+        let migrant = gsgp.pop().get_first().clone();
+        let kept = gsgp.adopt(migrant.clone(), &data.clone(), 1).unwrap();
+        assert!(kept.core().is_empty());
+        assert_eq!(kept.semantics(), migrant.semantics());
+
+        // the same problem and sizes, other instances.
+        let other = problems::koza(1).data(&mut rng_from_seed(2));
+        let (_, elsewhere) = hybrid(&other);
+        assert!(elsewhere.adopt(migrant.clone(), &data, 1).is_none());
+        let adopted = elsewhere.adopt(migrant.clone(), &data, usize::MAX).unwrap();
+        assert!(adopted.has_semantics_for(&other));
+        let mut program = migrant.reconstruct(usize::MAX).unwrap();
+        program.compute_semantics(&other);
+        assert_eq!(adopted.semantics(), program.semantics());
+        assert!(adopted.train_semantics() != migrant.train_semantics());
+    }
+
+    /// Returns whether some program of `gp` holds `marker`.
+    fn holds(gp: &GP, marker: &Node) -> bool {
+        gp.pop().core().iter().any(|i| i.core().contains(marker))
+    }
+
+    #[test]
+    fn async_migrants_arrive_at_their_destinations() {
+        let mut mgp = islands(3).set_migration_interval(1)
+            .set_island_migration_interval(2, 0)
+            .set_replacement(Replacement::Random);
+        mgp.init();
+        // island 0 holds the only programs with this constant.
+        let marker = Node::Constant(0.123);
+        let program = Individual::from_core(vec![Node::Addition, marker.clone(), Node::Input(0)]);
+        let data = mgp.subpops()[0].data().clone();
+        let program = mgp.subpops()[0].adopt(program, &data, 1).unwrap();
+        for ind in mgp.core[0].pop_mut().core_mut() {
+            *ind = program.clone();
+        }
+        assert!(!holds(&mgp.subpops()[1], &marker));
+        mgp.evolve_async(5);
+        assert!(holds(&mgp.subpops()[1], &marker));
+        // island 2 never emigrates, so it never takes in immigrants.
+        assert!(!holds(&mgp.subpops()[2], &marker));
+    }
+
+    #[test]
+    fn full_mailboxes_drop_batches_instead_of_blocking() {
+        let (done, finished) = mpsc::channel();
+        thread::spawn(move || {
+            // island 1 never reads its mailbox, which island 0 fills at every generation.
+            let mut mgp = islands(2).set_mailbox_capacity(1)
+                .set_migration_interval(1)
+                .set_island_migration_interval(1, 0);
+            mgp.init();
+            mgp.evolve_async(20);
+            let sizes: Vec<usize> = mgp.subpops().iter().map(|gp| gp.pop().size()).collect();
+            done.send(sizes).unwrap();
+        });
+        let sizes = finished.recv_timeout(Duration::from_secs(300))
+            .expect("evolve_async() did not terminate");
+        assert_eq!(sizes, vec![20, 20]);
+    }
+
+    /// Returns the generations of the records of each island.
+    fn recorded(mgp: &Mgp) -> Vec<Vec<usize>> {
+        mgp.history().iter().map(|h| h.iter().map(|s| s.generation).collect()).collect()
+    }
+
+    #[test]
+    fn history_and_best_follow_the_islands() {
+        let mut mgp = islands(2).set_migration_interval(2);
+        mgp.init();
+        mgp.evolve(5);
+        assert_eq!(mgp.generation(), 5);
+        assert_eq!(recorded(&mgp), vec![vec![0, 2, 4, 5]; 2]);
+        for (gp, history) in mgp.subpops().iter().zip(mgp.history()) {
+            let last = history.last().unwrap();
+            assert_eq!(last, &IslandStats::of(gp, 5));
+            assert_eq!(last.best, gp.pop().get_first().train());
+            let errors: Vec<Float> = gp.pop().core().iter().filter_map(|i| i.train()).collect();
+            let mean = errors.iter().sum::<Float>() / errors.len() as Float;
+            assert!((last.mean.unwrap() - mean).abs() <= 1e-4 * mean.abs());
+        }
+        let best = mgp.best().unwrap().train();
+        let bests: Vec<Option<Float>> = mgp.history().iter().map(|h| h[3].best).collect();
+        assert_eq!(best,
+                   bests.into_iter().min_by(|a, b| a.unwrap().total_cmp(&b.unwrap())).unwrap());
+
+        // a new `init()` starts over.
+        mgp.init();
+        assert_eq!((mgp.generation(), mgp.migrations().len()), (0, 0));
+        assert_eq!(recorded(&mgp), vec![vec![0]; 2]);
+    }
+
+    #[test]
+    fn islands_emigrate_at_their_own_intervals() {
+        let mut mgp = islands(2).set_migration_interval(2).set_island_migration_interval(1, 0);
+        mgp.init();
+        mgp.evolve(5);
+        let log: Vec<(usize, usize, usize)> =
+            mgp.migrations().iter().map(|m| (m.from, m.to, m.generation)).collect();
+        let mut expected = vec![(0, 1, 2); 4];
+        expected.extend(vec![(0, 1, 4); 4]);
+        assert_eq!(log, expected);
+
+        // `evolve_in_parallel()` makes island 1 emigrate as well, after its first turn.
+        let logged = mgp.migrations().len();
+        mgp.evolve_in_parallel(2, 3);
+        let log: Vec<(usize, usize)> =
+            mgp.migrations()[logged..].iter().map(|m| (m.from, m.generation)).collect();
+        assert_eq!(log.len(), 8);
+        assert!(log.iter().all(|&(_, generation)| generation == 8));
+        assert_eq!(log.iter().filter(|&&(from, _)| from == 1).count(), 4);
+        assert_eq!(mgp.generation(), 11);
+    }
+
+    #[test]
+    fn migration_log_records_survivors() {
+        let mut mgp = islands(2).set_migrants(Migrants::Count(10));
+        mgp.init();
+        mgp.evolve(2);
+        let before = programs(&mgp.subpops()[1]);
+        mgp.migrate(2, &[2, 0]);
+        let mut added = programs(&mgp.subpops()[1]);
+        for program in &before {
+            if let Some(at) = added.iter().position(|p| p == program) {
+                added.remove(at);
+            }
+        }
+        let log = mgp.migrations();
+        assert_eq!(log.len(), 10);
+        assert!(log.iter().all(|m| (m.from, m.to, m.generation) == (0, 1, 2)));
+        assert_eq!(log.iter().filter(|m| m.survived).count(), added.len());
+        // survivors replaced the worst residents (ties with the worst may go either way).
+        let worst = mgp.subpops()[1].pop().core().last().unwrap().train().unwrap();
+        for m in log {
+            let train = m.train.unwrap();
+            assert!(if m.survived { train <= worst } else { train >= worst }, "{:?}", m);
+        }
+    }
+
+    #[test]
+    fn async_history_and_log_follow_each_island() {
+        let mut mgp = islands(2).set_migration_interval(2).set_island_migration_interval(1, 3);
+        mgp.init();
+        mgp.evolve_async(7);
+        assert_eq!(mgp.generation(), 7);
+        assert_eq!(recorded(&mgp), vec![vec![0, 2, 4, 6, 7], vec![0, 3, 6, 7]]);
+        let log = mgp.migrations();
+        // immigrants are taken in when their destination emigrates.
+        for m in log {
+            assert_eq!(m.from, 1 - m.to);
+            assert!(if m.to == 0 { [2, 4, 6].contains(&m.generation) } else {
+                [3, 6].contains(&m.generation)
+            });
+        }
+        assert!(log.windows(2).all(|w| w[0].generation <= w[1].generation));
+        // island 0 emigrated before island 1 took in immigrants at generation 3.
+        assert!(log.iter().any(|m| m.to == 1));
+    }
+
+    fn yacht_islands(n: usize) -> Mgp {
+        let data = Data::new("yacht");
+        (0..n).fold(Mgp::new().set_seed(1), |mgp, i| {
+            mgp.add_subpop(GP::new_gp(data.clone()).set_pop_size(10).set_seed(i as u64))
+        })
+    }
+
+    #[test]
+    fn row_partitions_split_the_training_instances() {
+        let rows = Data::new("yacht").train().rows();
+        let mut mgp = yacht_islands(3).set_row_partition(RowPartition::Shards);
+        mgp.init();
+        let shards: Vec<usize> = mgp.subpops().iter().map(|gp| gp.data().train().rows()).collect();
+        assert_eq!(shards.iter().sum::<usize>(), rows);
+        assert!(shards.iter().all(|&r| r >= rows / 3 && r <= rows / 3 + 1));
+        for partition in &[RowPartition::Shared, RowPartition::Bootstrap] {
+            let mut mgp = yacht_islands(2).set_row_partition(*partition);
+            mgp.init();
+            assert!(mgp.subpops().iter().all(|gp| gp.data().train().rows() == rows));
+            assert_eq!(mgp.partitioned(), *partition != RowPartition::Shared);
+        }
+    }
+
+    #[test]
+    fn feature_partitions_select_the_inputs_of_each_island() {
+        let given = vec![vec![4, 0], vec![1, 2, 3]];
+        let mut mgp = yacht_islands(2).set_feature_partition(FeaturePartition::Given(given));
+        mgp.init();
+        assert_eq!(mgp.island_features, vec![Some(vec![4, 0]), Some(vec![1, 2, 3])]);
+        assert_eq!((mgp.subpops()[0].data().dims(), mgp.subpops()[1].data().dims()), (2, 3));
+
+        let mut mgp = yacht_islands(3).set_feature_partition(FeaturePartition::Random(2));
+        mgp.init();
+        for (gp, features) in mgp.subpops().iter().zip(&mgp.island_features) {
+            let features = features.as_ref().unwrap();
+            assert_eq!(gp.data().dims(), 2);
+            assert!(features[0] < features[1] && features[1] < 6);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Features are given for 1 of 2 islands")]
+    fn feature_partitions_must_cover_every_island() {
+        let given = FeaturePartition::Given(vec![vec![0]]);
+        yacht_islands(2).set_feature_partition(given).init();
+    }
+
+    #[test]
+    fn migrants_are_translated_to_the_inputs_of_their_destination() {
+        use core::node::Node::*;
+        let migrants = [Individual::from_core(vec![Addition, Input(0), Input(1)]),
+                        Individual::from_core(vec![Cosine, Input(0)]),
+                        Individual::from_core(vec![Multiplication, Input(0), Constant(2.0)])];
+        let cores = |inds: &[Individual]| -> Vec<Vec<Node>> {
+            inds.iter().map(|i| i.core().clone()).collect()
+        };
+
+        // island inputs 0 and 1 are variables 1 and 3, which are inputs 1 and 0 there.
+        let (sent, refused) = translate(&migrants, Some(&[1, 3]), Some(&[3, 1, 4]), 100);
+        assert_eq!(cores(&sent),
+                   vec![vec![Addition, Input(1), Input(0)],
+                        vec![Cosine, Input(1)],
+                        vec![Multiplication, Input(1), Constant(2.0)]]);
+        assert!(refused.is_empty());
+
+        // variable 3 is missing at the destination.
+        let (sent, refused) = translate(&migrants, Some(&[1, 3]), Some(&[1]), 100);
+        assert_eq!(cores(&sent),
+                   vec![vec![Cosine, Input(0)], vec![Multiplication, Input(0), Constant(2.0)]]);
+        assert_eq!(refused, vec![3]);
+
+        // from a subset to all variables, and over the reconstruction limit.
+        let (sent, refused) = translate(&migrants, Some(&[2, 5]), None, 2);
+        assert_eq!(cores(&sent), vec![vec![Cosine, Input(2)]]);
+        assert_eq!(refused, vec![3, 3]);
+    }
+
+    #[test]
+    fn disjoint_features_refuse_every_migrant() {
+        let mut mgp = yacht_islands(2)
+            .set_feature_partition(FeaturePartition::Given(vec![vec![0, 1, 2], vec![3, 4, 5]]));
+        mgp.init();
+        let migrants = mgp.subpops()[0].pop().clone_k_best(10);
+        let reads_inputs =
+            |i: &Individual| i.core().iter().any(|n| matches!(*n, Node::Input(_)));
+        let (from, to) = (mgp.island_features[0].as_deref(), mgp.island_features[1].as_deref());
+        let (sent, refused) = translate(&migrants, from, to, 10_000);
+        // only programs of constants alone get through.
+        assert!(!sent.iter().any(reads_inputs));
+        assert_eq!(refused.len(), migrants.iter().filter(|i| reads_inputs(i)).count());
+        assert!(!refused.is_empty());
+    }
+}
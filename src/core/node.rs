@@ -1,9 +1,10 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use core::utils::*;
+use core::Float;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// A node in a tree representation of an Individual.
-/// `Constant(f32)` and `Input(usize)` represent terminal nodes;
+/// `Constant(Float)` and `Input(usize)` represent terminal nodes;
 /// the remaining, functional / operator nodes.
 pub enum Node {
     Addition,
@@ -13,7 +14,7 @@ pub enum Node {
     Cosine,
     LogFunction,
     Input(usize),
-    Constant(f32),
+    Constant(Float),
 }
 
 impl Node {
@@ -29,7 +30,7 @@ impl Node {
     }
 
     /// performs operation of node on the args.
-    pub fn op(&self, args: Vec<Vec<f32>>) -> Vec<f32> {
+    pub fn op(&self, args: Vec<Vec<Float>>) -> Vec<Float> {
         if self.arity() != args.len() {
             panic!("Number of args does not match node's arity.")
         }
@@ -46,17 +47,15 @@ impl Node {
 
     /// Gets a random constant from `-1.0` to `1.0` in steps of `0.25`.
     /// Herein defined the constant set, a component of the terminal set.
-    pub fn get_random_const() -> Node {
-        let mut rng = thread_rng();
+    pub fn get_random_const<R: Rng>(rng: &mut R) -> Node {
         let constant_set = [-1.0, -0.75, -0.5, -0.25, 0.0, 0.25, 0.5, 0.75, 1.0];
         let i = rng.gen_range(0, constant_set.len());
-        Node::Constant(constant_set[i] as f32)
+        Node::Constant(constant_set[i] as Float)
     }
 
     /// Gets a random functional node uniformly at random from the functional set
     /// defined in `enum Node`. LogFunction is considered to be only part of GSGP.
-    pub fn get_random_functional() -> Node {
-        let mut rng = thread_rng();
+    pub fn get_random_functional<R: Rng>(rng: &mut R) -> Node {
         match rng.gen_range(0, 5) { // for now
             0 => Node::Addition,
             1 => Node::Subtraction,
@@ -67,8 +66,7 @@ impl Node {
         }
     }
 
-    pub fn get_random_input(dimensions: usize) -> Node {
-        let mut rng = thread_rng();
+    pub fn get_random_input<R: Rng>(dimensions: usize, rng: &mut R) -> Node {
         let i = rng.gen_range(0, dimensions);
         Node::Input(i)
     }
@@ -1,10 +1,11 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use std::cmp::Ordering;
 
 use core::individual::Individual;
 use core::data::Data;
 
 /// A container for a group of individuals.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Population {
     core: Vec<Individual>,
 }
@@ -51,7 +52,11 @@ impl Population {
     }
 
     /// Initializes a filled Population using ramped-half-half initialization
-    pub fn new_rhh(pop_size: usize, max_init_depth: usize, data: &Data) -> Population {
+    pub fn new_rhh<R: Rng>(pop_size: usize,
+                           max_init_depth: usize,
+                           data: &Data,
+                           rng: &mut R)
+                           -> Population {
         let mut p = Population::new();
         // Note: depth at root node is 0.
         // hence, #depths = #depth_groups = max_init_depth (maximum initial depth)
@@ -71,14 +76,14 @@ impl Population {
             }
             // fill depth group
             for _ in 0..full_indivs {
-                let mut i = Individual::full(depth, data);
+                let mut i = Individual::full(depth, data, rng);
                 i.compute_semantics(data); // only necessary for offline GSGP
                 i.compute_depth(); // only necessary for offline GSGP
                 p.core.push(i);
             }
 
             for _ in 0..grow_indivs {
-                let mut i = Individual::grow(depth, data);
+                let mut i = Individual::grow(depth, data, rng);
                 i.compute_semantics(data); // only necessary for offline GSGP
                 i.compute_depth(); // only necessary for offline GSGP
                 p.core.push(i);
@@ -87,16 +92,10 @@ impl Population {
         p
     }
 
-    /// Sorts population by training error.
+    /// Sorts population by training error, fittest first,
+    /// as compared by `Individual::cmp_train()`.
     pub fn sort_by_te(&mut self) {
-        // holy mother of hack
-        self.core
-            .sort_by_key(|i| {
-                             (i.train()
-                                  .expect("@Population::sort_by_te()
-        an Individual does not have training error computed.") *
-                              1_000_000f32) as u64
-                         });
+        self.core.sort_by(|a, b| a.cmp_train(b));
     }
 
     pub fn get_first(&self) -> &Individual {
@@ -104,7 +103,7 @@ impl Population {
     }
 
     pub fn clone_k_best(&self, k: usize) -> Vec<Individual> {
-        self.core.iter().take(k).map(|i| i.clone()).collect()
+        self.core.iter().take(k).cloned().collect()
     }
 
     pub fn keep_k_best(&mut self, k: usize) {
@@ -117,13 +116,12 @@ impl Population {
     ///
     /// - Draws a random sample.
     /// - Takes the fittest from the sample and returns a reference to it.
-    pub fn tournament_select(&self, pool_size: usize) -> &Individual {
-        let mut rng = thread_rng();
+    pub fn tournament_select<R: Rng>(&self, pool_size: usize, rng: &mut R) -> &Individual {
         let mut first_guy = &self.core[rng.gen_range(0, self.size())];
         for _ in 0..(pool_size - 1) {
             // There's possibly a closure for this with iter magic? :3
             let new_guy = &self.core[rng.gen_range(0, self.size())];
-            if new_guy.train() < first_guy.train() {
+            if new_guy.cmp_train(first_guy) == Ordering::Less {
                 first_guy = new_guy;
             }
         }
@@ -135,7 +133,7 @@ impl Population {
     /// - Draws a random sample.
     /// - Calculates fitness share for each individual.
     /// - Probabilistically selects an individual and returns a reference to it.
-    pub fn fitness_proportionate_select(&self, pool_size: usize) -> &Individual {
+    pub fn fitness_proportionate_select(&self, _pool_size: usize) -> &Individual {
         unimplemented!();
     }
 
@@ -145,11 +143,11 @@ impl Population {
     /// - Determines ranks within the sample.
     /// - Calculates rank-share for each individual
     /// - Probabilistically selects an individual and returns a reference to it
-    pub fn rank_select(&self, pool_size: usize) -> &Individual {
+    pub fn rank_select(&self, _pool_size: usize) -> &Individual {
         unimplemented!();
     }
 
-    pub fn pareto_rank_select(&self, pool_size: usize) -> &Individual {
+    pub fn pareto_rank_select(&self, _pool_size: usize) -> &Individual {
         unimplemented!();
     }
 }
@@ -0,0 +1,210 @@
+//! Semantics-aware pruning of introns, i.e. subtrees with a negligible effect on the output.
+//!
+//! Unlike `core::simplification`, which relies on algebraic rules only, pruning looks at
+//! the outputs on training data: a subtree is replaced by a constant (its mean output) or
+//! removed altogether (its parent collapses to the sibling argument) whenever the
+//! training error of the whole program degrades by less than a given tolerance.
+
+use core::data::Data;
+use core::individual::Individual;
+use core::node::Node;
+use core::fitness::Fitness;
+use core::Float;
+
+/// Summary of a pruning pass.
+#[derive(Debug, Clone)]
+pub struct PruningReport {
+    /// Size of the program before pruning.
+    pub size_before: usize,
+    /// Size of the program after pruning.
+    pub size_after: usize,
+    /// Training error before pruning.
+    pub train_before: Float,
+    /// Training error after pruning.
+    pub train_after: Float,
+}
+
+impl PruningReport {
+    /// Returns how many nodes were pruned.
+    pub fn size_reduction(&self) -> usize {
+        self.size_before - self.size_after
+    }
+
+    /// Returns the fraction of the original program that was pruned.
+    pub fn size_reduction_ratio(&self) -> f32 {
+        self.size_reduction() as f32 / self.size_before as f32
+    }
+}
+
+/// Prunes `ind` using the training data of `data`, with errors computed by `fitness`.
+///
+/// Every subtree (as delimited by `Individual::count_subtree_nodes`) is visited in prefix
+/// order and the following edits are tried, keeping the first one whose training error
+/// does not exceed the original one by `tolerance` or more:
+///
+/// - removing the subtree, i.e. replacing its binary parent by the sibling argument;
+/// - replacing the subtree by a constant holding its mean output on training data.
+///
+/// Errors are always compared against the original program, so that degradation does
+/// not accumulate beyond `tolerance`. The returned `Individual` is evaluated on `data`.
+/// Individuals without a `core` (i.e. evolved with `Variation::GeometricSemantic`)
+/// cannot be pruned.
+///
+/// If `ind` was evaluated with linear scaling, so are the original program and every
+/// candidate (see `Individual::evaluate_scaled()`): errors are those of the scaled
+/// outputs, and the returned `Individual` carries its own intercept and slope.
+pub fn prune(ind: &Individual,
+             data: &Data,
+             fitness: &dyn Fitness,
+             tolerance: Float)
+             -> (Individual, PruningReport) {
+    if ind.core().is_empty() {
+        panic!("@pruning::prune() Cannot prune an Individual without a core.");
+    }
+    let scaled = ind.scaling().is_some();
+    let mut best = evaluated(ind.clone_core(), data, fitness, scaled);
+    let train_before = best.train().unwrap();
+    let size_before = best.size();
+    let threshold = train_before + tolerance;
+
+    let mut idx = 0;
+    while idx < best.size() {
+        let acceptable = |c: &Individual| c.train().unwrap() < threshold;
+        let accepted = removal(&best, idx)
+            .map(|core| evaluated(core, data, fitness, scaled))
+            .filter(&acceptable)
+            .or_else(|| {
+                constant_replacement(&best, idx, data)
+                    .map(|core| evaluated(core, data, fitness, scaled))
+                    .filter(&acceptable)
+            });
+        match accepted {
+            // whatever lies at `idx` now deserves another look.
+            Some(pruned) => best = pruned,
+            None => idx += 1,
+        }
+    }
+
+    let report = PruningReport {
+        size_before,
+        size_after: best.size(),
+        train_before,
+        train_after: best.train().unwrap(),
+    };
+    (best, report)
+}
+
+/// Returns an evaluated `Individual` out of a program, with linear scaling if `scaled`.
+fn evaluated(core: Vec<Node>, data: &Data, fitness: &dyn Fitness, scaled: bool) -> Individual {
+    let mut i = Individual::from_core(core);
+    i.compute_semantics(data);
+    if scaled {
+        i.evaluate_scaled(data, fitness);
+    } else {
+        i.evaluate(data, fitness);
+    }
+    i
+}
+
+/// Returns the program of `ind` without the subtree at `idx`,
+/// in case the latter is an argument of a binary node.
+fn removal(ind: &Individual, idx: usize) -> Option<Vec<Node>> {
+    let parent = parent_of(ind, idx)?;
+    if ind.core()[parent].arity() != 2 {
+        return None;
+    }
+    let first = parent + 1;
+    let second = first + ind.count_subtree_nodes(first);
+    let sibling = if idx == first { second } else { first };
+
+    let mut core = ind.outer_left_copy(parent);
+    core.extend(ind.copy_subtree(sibling, ind.count_subtree_nodes(sibling)));
+    core.extend(ind.outer_right_copy(parent + ind.count_subtree_nodes(parent)));
+    Some(core)
+}
+
+/// Returns the program of `ind` with the subtree at `idx` replaced by its mean output,
+/// in case the subtree is not a terminal already.
+fn constant_replacement(ind: &Individual, idx: usize, data: &Data) -> Option<Vec<Node>> {
+    let subnodes = ind.count_subtree_nodes(idx);
+    if subnodes == 1 {
+        return None;
+    }
+    let mut subtree = Individual::from_core(ind.copy_subtree(idx, subnodes));
+    subtree.compute_semantics(data);
+    let sems = subtree.train_semantics();
+    let mean = sems.iter().sum::<Float>() / sems.len() as Float;
+    if !mean.is_finite() {
+        return None;
+    }
+
+    let mut core = ind.outer_left_copy(idx);
+    core.push(Node::Constant(mean));
+    core.extend(ind.outer_right_copy(idx + subnodes));
+    Some(core)
+}
+
+/// Returns the index of the parent of the node at `idx`, i.e. the closest preceding
+/// node whose subtree encloses `idx`.
+fn parent_of(ind: &Individual, idx: usize) -> Option<usize> {
+    (0..idx)
+        .rev()
+        .find(|&p| idx < p + ind.count_subtree_nodes(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fitness::Metric;
+    use core::utils::rng_from_seed;
+
+    #[test]
+    fn pruning_removes_introns_within_tolerance() {
+        use core::node::Node::*;
+        let data = Data::new("yacht");
+        // x0 + 0.001 * x1, whose second argument barely matters.
+        let ind = Individual::from_core(vec![Addition,
+                                             Input(0),
+                                             Multiplication,
+                                             Constant(0.001),
+                                             Input(1)]);
+        let (pruned, report) = prune(&ind, &data, &Metric::Rmse, 0.01);
+        assert_eq!((report.size_before, report.size_after), (5, pruned.size()));
+        assert!(!pruned.core().contains(&Input(1)));
+        assert!(report.train_after < report.train_before + 0.01);
+        assert_eq!(pruned.train(), Some(report.train_after));
+    }
+
+    #[test]
+    fn pruning_without_tolerance_never_degrades() {
+        let data = Data::new("yacht");
+        let mut rng = rng_from_seed(1);
+        for _ in 0..50 {
+            let ind = Individual::grow(4, &data, &mut rng);
+            let (pruned, report) = prune(&ind, &data, &Metric::Rmse, 0.0);
+            assert!(report.size_after <= report.size_before);
+            assert!(report.train_after <= report.train_before);
+            assert_eq!(pruned.train(), Some(report.train_after));
+        }
+    }
+
+    #[test]
+    fn pruning_keeps_linear_scaling() {
+        use core::node::Node::*;
+        let data = Data::new("yacht");
+        // x5 + 0.001 * x5, a multiple of x5 and thus as good as x5 once scaled.
+        let mut ind = Individual::from_core(vec![Addition,
+                                                 Input(5),
+                                                 Multiplication,
+                                                 Constant(0.001),
+                                                 Input(5)]);
+        ind.compute_semantics(&data);
+        ind.evaluate_scaled(&data, &Metric::Rmse);
+
+        let (pruned, report) = prune(&ind, &data, &Metric::Rmse, 0.01);
+        assert_eq!(*pruned.core(), vec![Input(5)]);
+        assert!((report.train_before - ind.train().unwrap()).abs() < 1e-3);
+        assert!((report.train_after - report.train_before).abs() < 1e-3);
+        assert!(pruned.scaling().is_some(), "pruned individual lost its scaling");
+    }
+}
@@ -0,0 +1,134 @@
+//! Algebraic simplification of programs, to reduce the size of evolved `Individual`s.
+//!
+//! Rewrites are applied bottom-up and only those preserving the semantics of `Node::op`
+//! (for finite values) are performed:
+//!
+//! - constant folding, computed with `Node::op` itself so that protected division
+//!   behaves exactly as during evaluation;
+//! - identities: `x + 0`, `x - 0`, `x * 1`, `x / 1` become `x`;
+//! - annihilators: `x * 0`, `0 / x` and `x - x` become `0`;
+//! - protected division by a constant not above `PROTECTED_DIVISION_LIMIT`
+//!   becomes its numerator;
+//! - canonical ordering of the arguments of commutative nodes, so that
+//!   equivalent subtrees are written alike.
+
+use std::cmp::Ordering;
+use core::node::Node;
+use core::tree::Tree;
+use core::utils::PROTECTED_DIVISION_LIMIT;
+use core::Float;
+
+/// Simplifies a tree bottom-up. See the module documentation for the rewrites performed.
+pub fn simplify_tree(tree: Tree) -> Tree {
+    let Tree { node, children } = tree;
+    let children = children.into_iter().map(simplify_tree).collect();
+    rewrite(node, children)
+}
+
+/// Simplifies a node whose `children` are already simplified.
+fn rewrite(node: Node, mut children: Vec<Tree>) -> Tree {
+    if children.is_empty() {
+        return Tree::leaf(node);
+    }
+    if children.iter().all(|c| c.as_const().is_some()) {
+        let args = children.iter().map(|c| vec![c.as_const().unwrap()]).collect();
+        return Tree::leaf(Node::Constant(node.op(args)[0]));
+    }
+    if is_commutative(&node) {
+        children.sort_by(canonical_cmp);
+    }
+    // since at least one argument is not a constant, after sorting a constant argument
+    // of a commutative node can only be found at the left.
+    match node {
+        Node::Addition if is_const(&children[0], 0.0) => children.remove(1),
+        Node::Multiplication if is_const(&children[0], 1.0) => children.remove(1),
+        Node::Multiplication if is_const(&children[0], 0.0) => Tree::leaf(Node::Constant(0.0)),
+        Node::Subtraction if is_const(&children[1], 0.0) => children.remove(0),
+        Node::Subtraction if children[0] == children[1] => Tree::leaf(Node::Constant(0.0)),
+        Node::Division if is_const(&children[0], 0.0) => Tree::leaf(Node::Constant(0.0)),
+        Node::Division if is_const(&children[1], 1.0) => children.remove(0),
+        Node::Division if is_negligible(&children[1]) => children.remove(0),
+        _ => Tree::branch(node, children),
+    }
+}
+
+/// Returns whether the arguments of `node` can be swapped without changing its output.
+fn is_commutative(node: &Node) -> bool {
+    matches!(*node, Node::Addition | Node::Multiplication)
+}
+
+/// Returns whether `tree` is the constant `val`.
+fn is_const(tree: &Tree, val: Float) -> bool {
+    tree.as_const() == Some(val)
+}
+
+/// Returns whether `tree` is a constant that protected division replaces by `1.0`.
+fn is_negligible(tree: &Tree) -> bool {
+    tree.as_const()
+        .is_some_and(|d| d.abs() <= PROTECTED_DIVISION_LIMIT)
+}
+
+/// Total order used to sort the arguments of commutative nodes:
+/// constants first, then inputs, then functional subtrees,
+/// ties broken by the printed prefix notation.
+fn canonical_cmp(a: &Tree, b: &Tree) -> Ordering {
+    let rank = |t: &Tree| match t.node {
+        Node::Constant(_) => 0,
+        Node::Input(_) => 1,
+        _ => 2,
+    };
+    rank(a)
+        .cmp(&rank(b))
+        .then_with(|| format!("{:?}", a.to_prefix()).cmp(&format!("{:?}", b.to_prefix())))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::data::Data;
+    use core::individual::Individual;
+    use core::utils::rng_from_seed;
+    use core::Float;
+
+    /// Returns the training outputs of a program.
+    fn outputs(ind: &Individual, data: &Data) -> Vec<Float> {
+        let mut ind = Individual::from_core(ind.clone_core());
+        ind.compute_semantics(data);
+        ind.train_semantics()
+    }
+
+    /// Asserts that `a` and `b` agree wherever `a` is finite.
+    fn assert_same_outputs(a: &[Float], b: &[Float]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            if x.is_finite() {
+                assert!((x - y).abs() <= 1e-4 * x.abs().max(1.0), "{} != {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn simplification_preserves_outputs_of_random_trees() {
+        let data = Data::new("yacht");
+        let mut rng = rng_from_seed(2);
+        for _ in 0..500 {
+            let ind = Individual::grow(6, &data, &mut rng);
+            let simplified = ind.simplify();
+            assert!(simplified.size() <= ind.size());
+            assert_same_outputs(&outputs(&ind, &data), &outputs(&simplified, &data));
+        }
+    }
+
+    #[test]
+    fn simplification_removes_redundancy() {
+        use core::node::Node::*;
+        let data = Data::new("yacht");
+        // ((x0 - x0) + x1 * 1) / (cos(0) * 1)
+        let core = vec![Division, Addition, Subtraction, Input(0), Input(0), Multiplication,
+                        Input(1), Constant(1.0), Multiplication, Cosine, Constant(0.0),
+                        Constant(1.0)];
+        let ind = Individual::from_core(core);
+        let simplified = ind.simplify();
+        assert_eq!(*simplified.core(), vec![Input(1)]);
+        assert_same_outputs(&outputs(&ind, &data), &outputs(&simplified, &data));
+    }
+}
@@ -0,0 +1,151 @@
+use core::node::Node;
+use core::Float;
+
+/// An explicit (pointer based) tree view of the prefix `Vec<Node>` an `Individual` is made of.
+///
+/// The flat prefix representation is great for variation operators, since subtrees are
+/// contiguous slices, but it is awkward when one has to rewrite a program structurally.
+/// Tree transformations (e.g. `core::simplification`) convert to a `Tree`,
+/// do their work and convert back with `to_prefix()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tree {
+    pub node: Node,
+    pub children: Vec<Tree>,
+}
+
+impl Tree {
+    /// Returns a terminal (childless) tree.
+    pub fn leaf(node: Node) -> Tree {
+        Tree {
+            node,
+            children: vec![],
+        }
+    }
+
+    /// Returns a tree with `node` at its root applied to `children`.
+    pub fn branch(node: Node, children: Vec<Tree>) -> Tree {
+        if node.arity() != children.len() {
+            panic!("@Tree::branch() Number of children does not match node's arity.");
+        }
+        Tree { node, children }
+    }
+
+    /// Builds a tree out of a program written in prefix notation.
+    pub fn from_prefix(prefix: &[Node]) -> Tree {
+        let mut idx = 0;
+        let tree = Tree::inner_from_prefix(prefix, &mut idx);
+        if idx + 1 != prefix.len() {
+            panic!("@Tree::from_prefix() Program has trailing nodes.");
+        }
+        tree
+    }
+
+    /// Recursive component of `from_prefix()`.
+    fn inner_from_prefix(prefix: &[Node], idx: &mut usize) -> Tree {
+        let node = prefix[*idx].clone();
+        let mut children = vec![];
+        for _child_node in 0..node.arity() {
+            *idx += 1;
+            children.push(Tree::inner_from_prefix(prefix, idx));
+        }
+        Tree { node, children }
+    }
+
+    /// Flattens the tree back to prefix notation.
+    pub fn to_prefix(&self) -> Vec<Node> {
+        let mut prefix = vec![];
+        self.inner_to_prefix(&mut prefix);
+        prefix
+    }
+
+    /// Recursive component of `to_prefix()`.
+    fn inner_to_prefix(&self, prefix: &mut Vec<Node>) {
+        prefix.push(self.node.clone());
+        for child in &self.children {
+            child.inner_to_prefix(prefix);
+        }
+    }
+
+    /// Writes the tree as an infix expression where inputs are named `x0`, `x1`, ...
+    /// Protected division and the logistic function, which have no usual operator,
+    /// are written as the calls `pdiv(n, d)` and `logistic(x)`.
+    pub fn to_infix(&self) -> String {
+        let c = &self.children;
+        match self.node {
+            Node::Constant(val) => format!("{:?}", val),
+            Node::Input(j) => format!("x{}", j),
+            Node::Addition => format!("({} + {})", c[0].to_infix(), c[1].to_infix()),
+            Node::Subtraction => format!("({} - {})", c[0].to_infix(), c[1].to_infix()),
+            Node::Multiplication => format!("({} * {})", c[0].to_infix(), c[1].to_infix()),
+            Node::Division => format!("pdiv({}, {})", c[0].to_infix(), c[1].to_infix()),
+            Node::Cosine => format!("cos({})", c[0].to_infix()),
+            Node::LogFunction => format!("logistic({})", c[0].to_infix()),
+        }
+    }
+
+    /// Returns the number of nodes in the tree.
+    pub fn size(&self) -> usize {
+        1 + self.children.iter().map(|c| c.size()).sum::<usize>()
+    }
+
+    /// Returns the constant value in case the root is a `Node::Constant`.
+    pub fn as_const(&self) -> Option<Float> {
+        match self.node {
+            Node::Constant(val) => Some(val),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::data::Data;
+    use core::individual::Individual;
+    use core::utils::rng_from_seed;
+
+    #[test]
+    fn prefix_programs_survive_the_round_trip() {
+        let data = Data::new("yacht");
+        let mut rng = rng_from_seed(1);
+        for depth in 0..6 {
+            let ind = Individual::grow(depth, &data, &mut rng);
+            let tree = Tree::from_prefix(ind.core());
+            assert_eq!(tree.size(), ind.size());
+            assert_eq!(tree.to_prefix(), *ind.core());
+        }
+    }
+
+    #[test]
+    fn trees_are_built_from_their_children() {
+        use core::node::Node::*;
+        let tree = Tree::from_prefix(&[Addition, Cosine, Input(0), Constant(2.0)]);
+        let built = Tree::branch(Addition,
+                                 vec![Tree::branch(Cosine, vec![Tree::leaf(Input(0))]),
+                                      Tree::leaf(Constant(2.0))]);
+        assert_eq!(tree, built);
+        assert_eq!(tree.children[1].as_const(), Some(2.0));
+        assert_eq!(tree.as_const(), None);
+    }
+
+    #[test]
+    fn trees_are_written_in_infix_notation() {
+        use core::node::Node::*;
+        let tree = Tree::from_prefix(&[Addition,
+                                       Cosine,
+                                       Input(0),
+                                       Division,
+                                       Constant(2.0),
+                                       LogFunction,
+                                       Input(1)]);
+        assert_eq!(tree.to_infix(), "(cos(x0) + pdiv(2.0, logistic(x1)))");
+        let branch = Tree::branch(Subtraction, vec![Tree::leaf(Input(0)), Tree::leaf(Input(1))]);
+        assert_eq!(branch.to_infix(), "(x0 - x1)");
+    }
+
+    #[test]
+    #[should_panic(expected = "trailing nodes")]
+    fn trailing_nodes_are_refused() {
+        Tree::from_prefix(&[Node::Input(0), Node::Input(1)]);
+    }
+}
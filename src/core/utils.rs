@@ -1,52 +1,121 @@
-pub fn rmse(x: &Vec<f32>, y: &Vec<f32>) -> f32 {
+use rand::{Rng, SeedableRng, XorShiftRng};
+use core::Float;
+
+/// Returns a fast random number generator seeded deterministically from `seed`.
+pub fn rng_from_seed(seed: u64) -> XorShiftRng {
+    // XorShift must not be seeded with zeros only, hence the constant words.
+    XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x9E37_79B9, 0x7F4A_7C15])
+}
+
+/// Returns a new generator seeded from `rng`, to be handed over to another task.
+pub fn split_rng<R: Rng>(rng: &mut R) -> XorShiftRng {
+    let mut seed: [u32; 4] = rng.gen();
+    if seed == [0; 4] {
+        seed[0] = 1;
+    }
+    XorShiftRng::from_seed(seed)
+}
+
+/// Returns `amount` elements of `iterable` drawn without replacement, in random order,
+/// or all of them if there are fewer.
+pub fn sample<T, I, R>(rng: &mut R, iterable: I, amount: usize) -> Vec<T>
+    where I: IntoIterator<Item = T>,
+          R: Rng
+{
+    let mut items: Vec<T> = iterable.into_iter().collect();
+    let amount = amount.min(items.len());
+    // a partial Fisher-Yates shuffle.
+    for i in 0..amount {
+        let j = rng.gen_range(i, items.len());
+        items.swap(i, j);
+    }
+    items.truncate(amount);
+    items
+}
+
+/// Converts to `f64`, for accumulations that need the extra precision in `f32` mode.
+#[allow(clippy::unnecessary_cast)] // a no-op with the `f64` feature
+#[inline]
+pub fn widen(v: Float) -> f64 {
+    v as f64
+}
+
+/// Denominators whose absolute value is not above this limit are replaced by `1.0`
+/// in protected division.
+pub const PROTECTED_DIVISION_LIMIT: Float = 0.00001; // this can totally influence semantics!
+
+pub fn rmse(x: &[Float], y: &[Float]) -> Float {
     // for semantics vs targets
     if x.len() != y.len() {
         // use assert!() ?
         panic!("cannot rmse vectors of different length!");
     }
-    let n = x.len() as f32;
-    let mapper = x.into_iter()
-        .zip(y.into_iter())
-        .map(|(x, y)| ((x - y) as f32).powi(2));
-    let se: f32 = mapper.sum(); // or .reduce(|| 0, |a, b| a + b)
+    let n = x.len() as Float;
+    let mapper = x.iter().zip(y).map(|(x, y)| (x - y).powi(2));
+    let se: Float = mapper.sum(); // or .reduce(|| 0, |a, b| a + b)
     (se / n).sqrt()
 }
 
-pub fn add(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
+/// Returns the intercept `a` and slope `b` that minimise the squared error of
+/// `a + b * x` against `y`. Accumulates in `f64`.
+/// For constant `x`, the slope is `0.0` and the intercept is the mean of `y`.
+pub fn linear_scaling_coefficients(x: &[Float], y: &[Float]) -> (Float, Float) {
+    if x.len() != y.len() {
+        panic!("cannot fit vectors of different length!");
+    }
+    let n = x.len() as f64;
+    let x_mean = x.iter().map(|v| widen(*v)).sum::<f64>() / n;
+    let y_mean = y.iter().map(|v| widen(*v)).sum::<f64>() / n;
+    let mut covariance = 0f64;
+    let mut variance = 0f64;
+    for (xi, yi) in x.iter().zip(y) {
+        let dx = widen(*xi) - x_mean;
+        covariance += dx * (widen(*yi) - y_mean);
+        variance += dx * dx;
+    }
+    let b = if variance > 0.0 && variance.is_finite() {
+        covariance / variance
+    } else {
+        0.0
+    };
+    let a = if b == 0.0 { y_mean } else { y_mean - b * x_mean };
+    (a as Float, b as Float)
+}
+
+/// Element-wise `a + b * x`.
+pub fn scale(x: &[Float], a: Float, b: Float) -> Vec<Float> {
+    x.iter().map(|v| a + b * v).collect()
+}
+
+pub fn add(mut x: Vec<Float>, y: Vec<Float>) -> Vec<Float> {
     if x.len() != y.len() {
         panic!("cannot sum element-wise vectors of different length!");
     } else {
-        x.into_iter()
-            .zip(y.into_iter())
-            .map(|(x, y)| x + y)
-            .collect()
+        add_assign(&mut x, &y);
+        x
     }
 }
 
-pub fn subtract(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
+pub fn subtract(mut x: Vec<Float>, y: Vec<Float>) -> Vec<Float> {
     if x.len() != y.len() {
         panic!("cannot subtract element-wise vectors of different length!");
     } else {
-        x.into_iter()
-            .zip(y.into_iter())
-            .map(|(x, y)| x - y)
-            .collect()
+        subtract_assign(&mut x, &y);
+        x
     }
 }
 
-pub fn multiply(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
+pub fn multiply(mut x: Vec<Float>, y: Vec<Float>) -> Vec<Float> {
     if x.len() != y.len() {
         panic!("cannot multiply element-wise vectors of different length!");
     } else {
-        x.into_iter() // or rayon::prelude::par_iter()
-            .zip(y.into_iter())
-            .map(|(x, y)| x * y)
-            .collect()
+        multiply_assign(&mut x, &y);
+        x
     }
 }
 
 /*
-fn dot_product(vec1: &[f32], vec2: &[f32]) -> f32 {
+fn dot_product(vec1: &[Float], vec2: &[Float]) -> Float {
     vec1.par_iter()
         .zip(vec2)
         .map(|e1, e2| e1 * e2)
@@ -54,32 +123,271 @@ fn dot_product(vec1: &[f32], vec2: &[f32]) -> f32 {
 }
 */
 
-pub fn cosine(x: Vec<f32>) -> Vec<f32> {
-    x.into_iter().map(|x| x.cos()).collect()
+pub fn cosine(mut x: Vec<Float>) -> Vec<Float> {
+    cosine_assign(&mut x);
+    x
+}
+
+/// Logistic function of a single value.
+pub fn logistic(y: Float) -> Float {
+    1.0 / (1.0 + (-y).exp())
 }
 
 /// Element-wise logistic function. Mainly for use of the Geometric Semantic Genetic Programming
-pub fn logistic_function(x: Vec<f32>) -> Vec<f32> {
-    let e = |y: f32| -> f32 { 1.0 / (1.0 + (-y).exp()) };
-    x.into_iter().map(e).collect()
+pub fn logistic_function(mut x: Vec<Float>) -> Vec<Float> {
+    logistic_assign(&mut x);
+    x
 }
 
+/// Protected division of a single numerator `n` by a denominator `d`.
+pub fn protected_division(n: Float, d: Float) -> Float {
+    if d.abs() > PROTECTED_DIVISION_LIMIT {
+        n / d
+    } else {
+        n / 1.0
+    }
+}
 
-pub fn divide(x: Vec<f32>, y: Vec<f32>) -> Vec<f32> {
+pub fn divide(mut x: Vec<Float>, y: Vec<Float>) -> Vec<Float> {
     if x.len() != y.len() {
         panic!("cannot divide element-wise vectors of different length!");
     }
-    let lower_limit = 0.00001; // this can totally influence semantics!
-    let protected_division = |(n, d): (f32, f32)| -> f32 {
-        // numerator and denominator
-        if d.abs() > lower_limit {
-            return n / d;
-        } else {
-            return n / 1.0;
+    divide_assign(&mut x, &y);
+    x
+}
+
+//  ---------------------------------------------------------------------  Slice kernels
+// In-place kernels over slices. They walk the data in fixed-size chunks of `LANES`
+// elements with no bounds checks inside a chunk, which lets the compiler vectorise
+// the arithmetic ones (all but `cos` and `exp`). They are used by `core::evaluator`,
+// and by the `Vec` based functions above.
+
+/// Number of elements processed per chunk by the slice kernels.
+pub const LANES: usize = 8;
+
+/// `x[i] = f(x[i], y[i])`.
+#[inline]
+pub fn zip_assign_left<F>(x: &mut [Float], y: &[Float], f: F)
+    where F: Fn(Float, Float) -> Float
+{
+    assert_eq!(x.len(), y.len(), "cannot operate on slices of different length!");
+    let mut xs = x.chunks_exact_mut(LANES);
+    let mut ys = y.chunks_exact(LANES);
+    for (xc, yc) in (&mut xs).zip(&mut ys) {
+        for k in 0..LANES {
+            xc[k] = f(xc[k], yc[k]);
+        }
+    }
+    for (a, b) in xs.into_remainder().iter_mut().zip(ys.remainder()) {
+        *a = f(*a, *b);
+    }
+}
+
+/// `y[i] = f(x[i], y[i])`.
+#[inline]
+pub fn zip_assign_right<F>(x: &[Float], y: &mut [Float], f: F)
+    where F: Fn(Float, Float) -> Float
+{
+    zip_assign_left(y, x, |b, a| f(a, b));
+}
+
+/// `out[i] = f(x[i], y[i])`.
+#[inline]
+pub fn zip_into<F>(out: &mut [Float], x: &[Float], y: &[Float], f: F)
+    where F: Fn(Float, Float) -> Float
+{
+    assert_eq!(x.len(), y.len(), "cannot operate on slices of different length!");
+    out.copy_from_slice(x);
+    zip_assign_left(out, y, f);
+}
+
+/// `x[i] = f(x[i])`.
+#[inline]
+pub fn map_assign<F>(x: &mut [Float], f: F)
+    where F: Fn(Float) -> Float
+{
+    let mut xs = x.chunks_exact_mut(LANES);
+    for xc in &mut xs {
+        for v in xc.iter_mut() {
+            *v = f(*v);
         }
-    };
-    x.into_iter()
-        .zip(y.into_iter())
-        .map(protected_division)
-        .collect()
+    }
+    for v in xs.into_remainder() {
+        *v = f(*v);
+    }
+}
+
+/// `out[i] = f(x[i])`.
+#[inline]
+pub fn map_into<F>(out: &mut [Float], x: &[Float], f: F)
+    where F: Fn(Float) -> Float
+{
+    out.copy_from_slice(x);
+    map_assign(out, f);
+}
+
+/// `x += y`, element-wise.
+pub fn add_assign(x: &mut [Float], y: &[Float]) {
+    zip_assign_left(x, y, |a, b| a + b);
+}
+
+/// `x -= y`, element-wise.
+pub fn subtract_assign(x: &mut [Float], y: &[Float]) {
+    zip_assign_left(x, y, |a, b| a - b);
+}
+
+/// `x *= y`, element-wise.
+pub fn multiply_assign(x: &mut [Float], y: &[Float]) {
+    zip_assign_left(x, y, |a, b| a * b);
+}
+
+/// `x /= y`, element-wise and protected.
+pub fn divide_assign(x: &mut [Float], y: &[Float]) {
+    zip_assign_left(x, y, protected_division);
+}
+
+/// `x = cos(x)`, element-wise.
+pub fn cosine_assign(x: &mut [Float]) {
+    map_assign(x, Float::cos);
+}
+
+/// `x = logistic(x)`, element-wise.
+pub fn logistic_assign(x: &mut [Float]) {
+    map_assign(x, logistic);
+}
+
+/// Solves `a x = b` by Gaussian elimination with partial pivoting.
+/// Returns `None` for (numerically) singular or non-finite systems.
+pub fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let p = b.len();
+    for col in 0..p {
+        let pivot = (col..p).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].is_nan() || a[pivot][col].abs() <= 1e-300 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[col] / pivot_row[col];
+            for (r, pv) in row.iter_mut().zip(pivot_row).skip(col) {
+                *r -= factor * pv;
+            }
+            b[col + 1 + offset] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0f64; p];
+    for row in (0..p).rev() {
+        let tail: f64 = ((row + 1)..p).map(|c| a[row][c] * x[c]).sum();
+        x[row] = (b[row] - tail) / a[row][row];
+    }
+    if x.iter().all(|v| v.is_finite()) {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type BinaryKernel = (fn(&mut [Float], &[Float]), fn(Float, Float) -> Float);
+    type UnaryKernel = (fn(&mut [Float]), fn(Float) -> Float);
+
+    /// Returns `n` values mixing signs, magnitudes and denominators near zero.
+    fn values(n: usize, seed: u64) -> Vec<Float> {
+        let mut rng = rng_from_seed(seed);
+        (0..n)
+            .map(|i| match i % 5 {
+                0 => 0.0,
+                1 => rng.gen_range(-1e-5, 1e-5),
+                _ => rng.gen_range(-10.0, 10.0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slice_kernels_match_scalar_loops() {
+        // lengths below, at and beyond multiples of `LANES`, for the remainders.
+        for n in 0..3 * LANES + 2 {
+            let (x, y) = (values(n, 1), values(n, 2));
+            let binary: [BinaryKernel; 4] =
+                [(add_assign, |a, b| a + b),
+                 (subtract_assign, |a, b| a - b),
+                 (multiply_assign, |a, b| a * b),
+                 (divide_assign, protected_division)];
+            for &(kernel, op) in &binary {
+                let mut out = x.clone();
+                kernel(&mut out, &y);
+                let expected: Vec<Float> = x.iter().zip(&y).map(|(a, b)| op(*a, *b)).collect();
+                assert_eq!(out, expected);
+                let mut right = y.clone();
+                zip_assign_right(&x, &mut right, op);
+                assert_eq!(right, expected);
+                let mut into = vec![0.0; n];
+                zip_into(&mut into, &x, &y, op);
+                assert_eq!(into, expected);
+            }
+            let unary: [UnaryKernel; 2] =
+                [(cosine_assign, Float::cos), (logistic_assign, logistic)];
+            for &(kernel, op) in &unary {
+                let mut out = x.clone();
+                kernel(&mut out);
+                let expected: Vec<Float> = x.iter().map(|a| op(*a)).collect();
+                assert_eq!(out, expected);
+                let mut into = vec![0.0; n];
+                map_into(&mut into, &x, op);
+                assert_eq!(into, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn samples_are_distinct_and_capped() {
+        let mut rng = rng_from_seed(1);
+        let mut drawn = sample(&mut rng, 10..30, 5);
+        assert_eq!(drawn.len(), 5);
+        drawn.sort();
+        drawn.dedup();
+        assert!(drawn.len() == 5 && drawn.iter().all(|v| (10..30).contains(v)));
+        let mut all = sample(&mut rng, 0..4, 10);
+        all.sort();
+        assert_eq!(all, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn division_is_protected_near_zero() {
+        assert_eq!(protected_division(3.0, 2.0), 1.5);
+        assert_eq!(protected_division(3.0, 1e-6), 3.0);
+        assert_eq!(protected_division(3.0, -PROTECTED_DIVISION_LIMIT), 3.0);
+        assert_eq!(divide(vec![1.0, 2.0], vec![0.0, 4.0]), vec![1.0, 0.5]);
+    }
+
+    #[test]
+    fn linear_scaling_coefficients_are_the_least_squares_fit() {
+        let x = values(50, 3);
+        // y = 1.5 - 0.25 * x, exactly.
+        let y: Vec<Float> = x.iter().map(|v| 1.5 - 0.25 * v).collect();
+        let (a, b) = linear_scaling_coefficients(&x, &y);
+        assert!((a - 1.5).abs() < 1e-5 && (b + 0.25).abs() < 1e-5);
+        assert!(rmse(&scale(&x, a, b), &y) < 1e-5);
+        // constant outputs only fit the mean.
+        assert_eq!(linear_scaling_coefficients(&[2.0; 4], &[1.0, 2.0, 3.0, 4.0]), (2.5, 0.0));
+    }
+
+    #[test]
+    fn rmse_of_known_residuals() {
+        assert_eq!(rmse(&[1.0, 2.0, 3.0, 4.0], &[1.0, 2.0, 3.0, 0.0]), 2.0);
+    }
+
+    #[test]
+    fn solve_matches_a_known_system() {
+        // 2x + y = 5, x + 3y = 10, after pivoting on the larger first column.
+        let a = vec![vec![1.0, 3.0], vec![2.0, 1.0]];
+        let x = solve(a, vec![10.0, 5.0]).unwrap();
+        assert!((x[0] - 1.0).abs() < 1e-12 && (x[1] - 3.0).abs() < 1e-12);
+        assert_eq!(solve(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]), None);
+    }
 }
@@ -1,3 +1,3 @@
 extern crate rand;
 extern crate rayon;
-mod core;
+pub mod core;
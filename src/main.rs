@@ -1,10 +1,8 @@
-extern crate rand;
-extern crate rayon;
+extern crate nodevo;
 
-mod core;
-use core::gp::GP;
-use core::data::Data;
-use core::multi_gp::Mgp;
+use nodevo::core::gp::GP;
+use nodevo::core::data::Data;
+use nodevo::core::multi_gp::Mgp;
 
 fn main() {
 